@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::board::LinesCleared;
+
+// 对战的攻击表及垃圾行规则，从assets/rules/*.attack.ron加载
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct AttackTable {
+    // 普通消行发送的垃圾行数，下标为消行数
+    pub lines: Vec<u32>,
+    // T-spin消行发送的垃圾行数，下标为消行数
+    pub t_spin: Vec<u32>,
+    // 连续困难消行的额外攻击
+    pub back_to_back: u32,
+    // 连击奖励，下标为连击数，超出时使用最后一项
+    pub combo: Vec<u32>,
+    // 全消的额外攻击
+    pub perfect_clear: u32,
+    // 垃圾行进入面板前的等待时间（秒）
+    pub garbage_delay: f32,
+    // 消行时是否先抵消等待中的垃圾行
+    pub cancel: bool,
+    // 每次锁定最多插入的垃圾行数
+    pub garbage_cap: u32,
+    // 同一次攻击的垃圾行之间空缺位置改变的概率
+    pub hole_change_chance: f64,
+}
+
+// 与assets/rules/guideline.attack.ron相同，资源未加载时使用
+impl Default for AttackTable {
+    fn default() -> Self {
+        AttackTable {
+            lines: vec![0, 0, 1, 2, 4],
+            t_spin: vec![0, 2, 4, 6],
+            back_to_back: 1,
+            combo: vec![0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5],
+            perfect_clear: 10,
+            garbage_delay: 1.0,
+            cancel: true,
+            garbage_cap: 8,
+            hole_change_chance: 0.0,
+        }
+    }
+}
+
+// 超出表长度时使用最后一项
+fn lookup(table: &[u32], index: usize) -> u32 {
+    table
+        .get(index)
+        .or(table.last())
+        .copied()
+        .unwrap_or_default()
+}
+
+impl AttackTable {
+    // 消四和T-spin消行为困难消行，可以连续获得额外攻击
+    pub fn is_difficult(lines_cleared: &LinesCleared) -> bool {
+        lines_cleared.count >= 4 || (lines_cleared.t_spin && lines_cleared.count > 0)
+    }
+
+    // 一次消行的攻击，combo为本次消行前已连续消行的次数，
+    // back_to_back为上一次消行是否为困难消行
+    pub fn attack(&self, lines_cleared: &LinesCleared, combo: u32, back_to_back: bool) -> u32 {
+        if lines_cleared.count == 0 {
+            return 0;
+        }
+        let count = lines_cleared.count as usize;
+        let mut attack = if lines_cleared.t_spin {
+            lookup(&self.t_spin, count)
+        } else {
+            lookup(&self.lines, count)
+        };
+        if back_to_back && AttackTable::is_difficult(lines_cleared) {
+            attack += self.back_to_back;
+        }
+        attack += lookup(&self.combo, combo as usize);
+        if lines_cleared.perfect_clear {
+            attack += self.perfect_clear;
+        }
+        attack
+    }
+
+    pub fn garbage_delay(&self) -> Duration {
+        Duration::from_secs_f32(self.garbage_delay.max(0.0))
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct AttackTableHandle(pub Handle<AttackTable>);
+
+pub fn setup_attack_table(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AttackTableHandle(
+        asset_server.load("rules/guideline.attack.ron"),
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines_cleared(count: u32, t_spin: bool) -> LinesCleared {
+        LinesCleared {
+            count,
+            perfect_clear: false,
+            t_spin,
+            chain: 0,
+        }
+    }
+
+    #[test]
+    fn guideline_attack_table() {
+        let table: AttackTable =
+            ron::from_str(include_str!("../assets/rules/guideline.attack.ron"))
+                .expect("valid attack table");
+        // 单消、双消、三消、消四
+        let attacks: Vec<u32> = (1..=4)
+            .map(|count| table.attack(&lines_cleared(count, false), 0, false))
+            .collect();
+        assert_eq!(attacks, [0, 1, 2, 4]);
+        assert_eq!(table.attack(&lines_cleared(0, false), 5, true), 0);
+
+        // 连续的消四和T-spin消行获得B2B奖励，普通消行没有
+        assert_eq!(table.attack(&lines_cleared(4, false), 0, true), 5);
+        assert_eq!(table.attack(&lines_cleared(2, true), 0, false), 4);
+        assert_eq!(table.attack(&lines_cleared(2, true), 0, true), 5);
+        assert_eq!(table.attack(&lines_cleared(2, false), 0, true), 1);
+
+        // 连击奖励按连击数查表，超出表长度时使用最后一项
+        let combos: Vec<u32> = (0..=13)
+            .map(|combo| table.attack(&lines_cleared(1, false), combo, false))
+            .collect();
+        assert_eq!(combos, [0, 0, 1, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5]);
+
+        // 全消额外发送
+        let perfect_clear = LinesCleared {
+            perfect_clear: true,
+            ..lines_cleared(4, false)
+        };
+        assert_eq!(table.attack(&perfect_clear, 0, false), 14);
+    }
+}
@@ -0,0 +1,270 @@
+use std::time::Duration;
+
+use bevy::input::mouse::MouseMotion;
+use bevy::prelude::*;
+
+use crate::board::*;
+use crate::bot::*;
+use crate::cpu::*;
+use crate::engine::{ActivePiece, Game};
+use crate::menu::OnMainMenuScreen;
+use crate::piece::*;
+use crate::stats::StatsBoardRow;
+use crate::tbp::{TbpBot, TbpBotCommand};
+use crate::timing::*;
+use crate::variant::BoardRoot;
+use crate::versus::*;
+
+// 主菜单无操作多久后开始演示
+const ATTRACT_IDLE_TIME: Duration = Duration::from_secs(20);
+// 演示使用的电脑难度
+const DEMO_DIFFICULTY: usize = 2;
+// AI游玩时每个骨牌出现后等待的时间，便于看清
+const AI_PLAY_PIECE_DELAY: Duration = Duration::from_millis(150);
+// AI游玩时考虑的骨牌数
+const AI_PLAY_DEPTH: usize = 2;
+
+// 主菜单的无操作计时
+#[derive(Debug, Resource)]
+pub struct AttractTimer(pub Timer);
+
+impl Default for AttractTimer {
+    fn default() -> Self {
+        AttractTimer(Timer::new(ATTRACT_IDLE_TIME, TimerMode::Once))
+    }
+}
+
+// 主菜单背景中由电脑游玩的演示面板
+#[derive(Debug, Component)]
+pub struct DemoGame;
+
+// 游戏中是否由AI操作骨牌，用于调试规则
+#[derive(Debug, Default, Resource)]
+pub struct AiPlay(pub bool);
+
+// AI游玩使用的外部机器人，开启AI游玩时启动
+#[derive(Debug, Default, Resource)]
+pub struct AiPlayBot(pub Option<TbpBot>);
+
+fn spawn_demo_game(commands: &mut Commands, weights: BotWeights) {
+    let board_size = BoardSize::default();
+    commands
+        .spawn((
+            DemoGame,
+            Transform::default(),
+            Visibility::Visible,
+            PlayerGame(Game::new(board_size, rand::random())),
+            CpuPlayer::new(CPU_DIFFICULTY_PRESETS[DEMO_DIFFICULTY]).with_weights(weights),
+        ))
+        .with_children(|parent| {
+            spawn_player_borders(parent, &board_size);
+            parent.spawn((
+                Text2d::new("DEMO - press any key"),
+                TextFont {
+                    font_size: 25.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                Transform::from_xyz(0.0, board_size.height() / 2.0 + 30.0, 0.0),
+            ));
+        });
+}
+
+// 主菜单无操作一段时间后隐藏菜单和单人面板，开始演示，有任意输入时回到菜单
+pub fn update_attract_mode(
+    mut commands: Commands,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut attract_timer: ResMut<AttractTimer>,
+    bot_weights_handle: Res<BotWeightsHandle>,
+    bot_weights: Res<Assets<BotWeights>>,
+    q_demo: Query<Entity, With<DemoGame>>,
+    mut q_menu: Query<&mut Visibility, (With<OnMainMenuScreen>, Without<DemoGame>)>,
+    mut q_board: Query<
+        &mut Visibility,
+        (
+            Or<(With<BoardRoot>, With<StatsBoardRow>)>,
+            Without<OnMainMenuScreen>,
+            Without<DemoGame>,
+        ),
+    >,
+    time: Res<Time>,
+) {
+    let any_input = keyboard_input.get_just_pressed().next().is_some()
+        || mouse_input.get_just_pressed().next().is_some()
+        || mouse_motion.read().count() > 0;
+    let visibility = if q_demo.is_empty() {
+        if any_input {
+            attract_timer.0.reset();
+            return;
+        }
+        attract_timer.0.tick(time.delta());
+        if !attract_timer.0.just_finished() {
+            return;
+        }
+        info!("attract mode: demo started");
+        spawn_demo_game(
+            &mut commands,
+            current_bot_weights(&bot_weights_handle, &bot_weights),
+        );
+        Visibility::Hidden
+    } else {
+        if !any_input {
+            return;
+        }
+        info!("attract mode: back to menu");
+        for entity in &q_demo {
+            commands.entity(entity).despawn();
+        }
+        attract_timer.0.reset();
+        Visibility::Inherited
+    };
+    for mut menu_visibility in q_menu.iter_mut().chain(q_board.iter_mut()) {
+        *menu_visibility = visibility;
+    }
+}
+
+// 电脑游玩演示面板，堆到顶部时重新开始
+pub fn play_demo(
+    mut q_demo: Query<(&mut PlayerGame, &mut CpuPlayer), With<DemoGame>>,
+    time: Res<Time>,
+) {
+    for (mut player_game, mut cpu) in &mut q_demo {
+        let game = &mut player_game.bypass_change_detection().0;
+        let mut changed = false;
+        if game.piece.is_none() {
+            changed = true;
+            if !game.spawn_piece() {
+                *game = Game::new(game.board_size, rand::random());
+            }
+        }
+        let (moved, locked) = cpu.play(game, time.delta());
+        if changed || moved || locked.is_some() {
+            player_game.set_changed();
+        }
+    }
+}
+
+pub fn reset_attract_timer(mut attract_timer: ResMut<AttractTimer>) {
+    attract_timer.0.reset();
+}
+
+// 按ECS中的面板、当前骨牌和预览队列建立对局，面板方块的颜色不保留
+pub fn snapshot_game(
+    board_size: BoardSize,
+    board: impl Iterator<Item = Block>,
+    piece: ActivePiece,
+    piece_queue: &PieceQueue,
+) -> Game {
+    let mut game = Game::new(board_size, 0);
+    game.board = board.map(|block| (GARBAGE_COLOR, block)).collect();
+    game.piece = Some(piece);
+    game.queue = piece_queue
+        .0
+        .iter()
+        .map(|piece_config| piece_config.piece_type)
+        .collect();
+    game
+}
+
+// 按I键开关AI游玩
+pub fn toggle_ai_play(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    tbp_bot_command: Res<TbpBotCommand>,
+    board_size: Res<BoardSize>,
+    mut ai_play: ResMut<AiPlay>,
+    mut ai_play_bot: ResMut<AiPlayBot>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyI) {
+        ai_play.0 = !ai_play.0;
+        info!("ai play: {}", ai_play.0);
+        // 外部机器人只用于标准面板
+        ai_play_bot.0 = if ai_play.0 && *board_size == BoardSize::default() {
+            tbp_bot_command.spawn()
+        } else {
+            None
+        };
+    }
+}
+
+// AI按当前面板搜索落点，与手动操作一样经过移动、旋转和锁定的规则
+// 只支持标准四格骨牌，指定了外部机器人且为标准面板时由它给出落点
+pub fn play_ai(
+    ai_play: Res<AiPlay>,
+    mut ai_play_bot: ResMut<AiPlayBot>,
+    mut q_piece: Query<(&PieceType, &mut Block, &mut Transform, &mut RotationState)>,
+    q_board: Query<&Block, Without<PieceType>>,
+    board_size: Res<BoardSize>,
+    piece_queue: Res<PieceQueue>,
+    bot_weights_handle: Res<BotWeightsHandle>,
+    bot_weights: Res<Assets<BotWeights>>,
+    mut timers: ResMut<GameTimers>,
+    mut last_move_is_rotation: ResMut<LastMoveIsRotation>,
+    mut waited: Local<Duration>,
+    time: Res<Time>,
+) {
+    let Some((piece_type, _, _, rotation_state)) = q_piece.iter().next() else {
+        *waited = Duration::ZERO;
+        return;
+    };
+    if !ai_play.0 || matches!(piece_type, PieceType::Custom(_)) {
+        return;
+    }
+    *waited += time.delta();
+    if *waited < AI_PLAY_PIECE_DELAY {
+        return;
+    }
+
+    let mut game = snapshot_game(
+        *board_size,
+        q_board.iter().copied(),
+        ActivePiece {
+            piece_type: *piece_type,
+            blocks: q_piece.iter().map(|(_, block, _, _)| *block).collect(),
+            state: rotation_state.0,
+        },
+        &piece_queue,
+    );
+    // TBP协议按标准10列面板描述局面，其他面板大小使用内置搜索
+    let placement = match ai_play_bot.0.as_mut() {
+        Some(bot) if *board_size == BoardSize::default() => bot.poll(&game),
+        _ => best_placement(
+            &game,
+            AI_PLAY_DEPTH,
+            &current_bot_weights(&bot_weights_handle, &bot_weights),
+        ),
+    };
+    let Some(placement) = placement else {
+        return;
+    };
+    *waited = Duration::ZERO;
+    // 执行硬降之前的输入，再落到底部立即锁定
+    let mut last_input = None;
+    for input in placement
+        .inputs
+        .iter()
+        .filter(|input| **input != BotInput::HardDrop)
+    {
+        if apply_input(&mut game, *input) {
+            last_input = Some(*input);
+        }
+    }
+    game.drop_piece(game.drop_distance());
+    let Some(piece) = game.piece else {
+        return;
+    };
+    for (index, (_, mut block, mut transform, mut rotation_state)) in q_piece.iter_mut().enumerate()
+    {
+        *block = piece.blocks[index];
+        transform.translation = block.translation(&board_size);
+        rotation_state.0 = piece.state;
+    }
+    last_move_is_rotation.0 = last_input == Some(BotInput::Rotate);
+    finish_timer(&mut timers.lock);
+}
+
+pub fn reset_ai_play(mut ai_play: ResMut<AiPlay>, mut ai_play_bot: ResMut<AiPlayBot>) {
+    ai_play.0 = false;
+    ai_play_bot.0 = None;
+}
@@ -0,0 +1,83 @@
+use std::io::{self, BufRead, Write};
+
+use tetris::board::BoardSize;
+use tetris::bot::*;
+use tetris::engine::Game;
+use tetris::tbp::*;
+
+// 用于测试的TBP机器人，使用内置的落点搜索，只考虑当前骨牌
+// 运行游戏时指定：cargo run -- --tbp-bot target/debug/tbp_dummy_bot
+
+fn send(message: &BotMessage) {
+    let line = serde_json::to_string(message).expect("serializable message");
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", line);
+    let _ = stdout.flush();
+}
+
+fn suggest(game: &Game) -> Vec<Move> {
+    let Some(piece) = &game.piece else {
+        return Vec::new();
+    };
+    best_placement(game, 1, &BotWeights::default())
+        .and_then(|placement| placement_move(piece.piece_type, &placement))
+        .into_iter()
+        .collect()
+}
+
+// 按对方的操作放置当前骨牌，再取出下一个已知骨牌
+fn play(game: &mut Game, mv: &Move) {
+    let Some(placement) = find_placement(game, mv) else {
+        eprintln!("tbp_dummy_bot: unreachable move {:?}", mv);
+        return;
+    };
+    let (after, _) = simulate(game, &placement);
+    *game = after;
+    spawn_known_piece(game);
+}
+
+fn main() {
+    send(&BotMessage::Info {
+        name: "tetris dummy bot".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        author: "NightsWatchGames".to_string(),
+        features: Vec::new(),
+    });
+    let mut game: Option<Game> = None;
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let message = match serde_json::from_str::<FrontendMessage>(&line) {
+            Ok(message) => message,
+            Err(err) => {
+                eprintln!("tbp_dummy_bot: invalid message {:?}: {}", line, err);
+                continue;
+            }
+        };
+        match message {
+            FrontendMessage::Rules => send(&BotMessage::Ready),
+            FrontendMessage::Start(start) => {
+                game = Some(game_from_start(&start, BoardSize::default()));
+            }
+            FrontendMessage::Stop => game = None,
+            FrontendMessage::Suggest => send(&BotMessage::Suggestion {
+                moves: game.as_ref().map(suggest).unwrap_or_default(),
+            }),
+            FrontendMessage::Play { mv } => {
+                if let Some(game) = game.as_mut() {
+                    play(game, &mv);
+                }
+            }
+            FrontendMessage::NewPiece { piece } => {
+                if let Some(game) = game.as_mut() {
+                    game.queue.push_back(piece);
+                    if game.piece.is_none() {
+                        spawn_known_piece(game);
+                    }
+                }
+            }
+            FrontendMessage::Quit => break,
+        }
+    }
+}
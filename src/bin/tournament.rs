@@ -0,0 +1,270 @@
+use std::process::exit;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use tetris::attack::AttackTable;
+use tetris::cpu::CPU_DIFFICULTY_PRESETS;
+use tetris::simulation::*;
+
+// 无界面的机器人比赛，使用固定的种子，输出每个机器人的汇总结果
+// 搜索较慢，建议使用release构建：cargo run --release --bin tournament -- --games 20
+
+const USAGE: &str = "usage: tournament [--mode versus|sprint|marathon] [--bot <preset>[=<weights file>]]... \
+[--games <n>] [--seed <n>] [--max-pieces <n>] [--max-time <secs>] [--rules <file>] [--format csv|json]
+presets: easy, normal, hard, expert (default: all)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Versus,
+    Solo(SoloGoal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Debug)]
+struct Options {
+    mode: Mode,
+    bots: Vec<BotConfig>,
+    games: u64,
+    seed: u64,
+    max_pieces: u32,
+    max_time: Duration,
+    rules: String,
+    format: Format,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("tournament: {}\n{}", message, USAGE);
+    exit(2);
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| usage_error(&format!("{} expects a number", name)))
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        mode: Mode::Versus,
+        bots: Vec::new(),
+        games: 10,
+        seed: 0,
+        max_pieces: 500,
+        max_time: Duration::from_secs(300),
+        rules: "assets/rules/guideline.attack.ron".to_string(),
+        format: Format::Csv,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" => {
+                options.mode = match args.next().as_deref() {
+                    Some("versus") => Mode::Versus,
+                    Some("sprint") => Mode::Solo(SoloGoal::Sprint),
+                    Some("marathon") => Mode::Solo(SoloGoal::Marathon),
+                    _ => usage_error("unknown mode"),
+                }
+            }
+            "--bot" => {
+                let value = args.next().unwrap_or_default();
+                let (name, weights_path) = match value.split_once('=') {
+                    Some((name, weights_path)) => (name, Some(weights_path)),
+                    None => (value.as_str(), None),
+                };
+                let Some(preset) = CPU_DIFFICULTY_PRESETS
+                    .iter()
+                    .find(|preset| preset.name.eq_ignore_ascii_case(name))
+                else {
+                    usage_error(&format!("unknown bot {:?}", name));
+                };
+                let mut bot = BotConfig::from_difficulty(preset);
+                if let Some(weights_path) = weights_path {
+                    bot.weights = load_weights(weights_path).unwrap_or_else(|err| {
+                        eprintln!("tournament: invalid weights {}", err);
+                        exit(1);
+                    });
+                    bot.name = format!("{}({})", bot.name, weights_path);
+                }
+                options.bots.push(bot);
+            }
+            "--games" => options.games = parse_number(&arg, args.next()),
+            "--seed" => options.seed = parse_number(&arg, args.next()),
+            "--max-pieces" => options.max_pieces = parse_number(&arg, args.next()),
+            "--max-time" => {
+                options.max_time = Duration::from_secs(parse_number(&arg, args.next()));
+            }
+            "--rules" => {
+                options.rules = args
+                    .next()
+                    .unwrap_or_else(|| usage_error("--rules expects a file"));
+            }
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("csv") => Format::Csv,
+                    Some("json") => Format::Json,
+                    _ => usage_error("unknown format"),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => usage_error(&format!("unknown argument {:?}", arg)),
+        }
+    }
+    if options.bots.is_empty() {
+        options.bots = CPU_DIFFICULTY_PRESETS
+            .iter()
+            .map(BotConfig::from_difficulty)
+            .collect();
+    }
+    if options.mode == Mode::Versus && options.bots.len() < 2 {
+        usage_error("versus needs at least two bots");
+    }
+    options
+}
+
+// 与游戏相同的攻击表，文件不存在时使用默认规则
+fn load_attack_table(path: &str) -> AttackTable {
+    match std::fs::read_to_string(path) {
+        Ok(content) => ron::from_str(&content).unwrap_or_else(|err| {
+            eprintln!("tournament: invalid attack table {}: {}", path, err);
+            exit(1);
+        }),
+        Err(err) => {
+            eprintln!("tournament: {}: {}, using default rules", path, err);
+            AttackTable::default()
+        }
+    }
+}
+
+// 一个机器人（对战中为一组对手）的汇总结果
+#[derive(Debug, Default, Serialize)]
+struct Summary {
+    mode: String,
+    bot: String,
+    opponent: String,
+    games: u32,
+    wins: u32,
+    losses: u32,
+    // 对战超时或单人模式未达到目标且未堆到顶部
+    draws: u32,
+    pps: f32,
+    apm: f32,
+    // 平均每局消除的行数
+    lines: f32,
+    // 平均每局的时间（秒）
+    seconds: f32,
+}
+
+impl Summary {
+    fn new(mode: &str, bot: &str, opponent: &str, results: &[GameResult]) -> Self {
+        let games = results.len() as f32;
+        let total = |f: fn(&GameResult) -> f32| results.iter().map(f).sum::<f32>();
+        let seconds = total(|result| result.seconds);
+        Summary {
+            mode: mode.to_string(),
+            bot: bot.to_string(),
+            opponent: opponent.to_string(),
+            games: results.len() as u32,
+            wins: results.iter().filter(|result| result.won).count() as u32,
+            losses: results.iter().filter(|result| result.topped_out).count() as u32,
+            draws: results
+                .iter()
+                .filter(|result| !result.won && !result.topped_out)
+                .count() as u32,
+            pps: total(|result| result.pieces as f32) / seconds.max(f32::EPSILON),
+            apm: total(|result| result.attack as f32) * 60.0 / seconds.max(f32::EPSILON),
+            lines: total(|result| result.lines as f32) / games.max(1.0),
+            seconds: seconds / games.max(1.0),
+        }
+    }
+}
+
+fn run(options: &Options, table: &AttackTable) -> Vec<Summary> {
+    let seeds: Vec<u64> = (0..options.games)
+        .map(|game| options.seed.wrapping_add(game))
+        .collect();
+    match options.mode {
+        Mode::Solo(goal) => {
+            let mode = match goal {
+                SoloGoal::Sprint => "sprint",
+                SoloGoal::Marathon => "marathon",
+            };
+            options
+                .bots
+                .iter()
+                .map(|bot| {
+                    let results = parallel_map(&seeds, |seed| {
+                        run_solo(bot, goal, *seed, options.max_pieces, table)
+                    });
+                    Summary::new(mode, &bot.name, "", &results)
+                })
+                .collect()
+        }
+        Mode::Versus => {
+            let mut summaries = Vec::new();
+            for (index, first) in options.bots.iter().enumerate() {
+                for second in &options.bots[index + 1..] {
+                    let results = parallel_map(&seeds, |seed| {
+                        run_versus([first, second], *seed, options.max_time, table)
+                    });
+                    let first_results: Vec<_> = results.iter().map(|result| result[0]).collect();
+                    let second_results: Vec<_> = results.iter().map(|result| result[1]).collect();
+                    summaries.push(Summary::new(
+                        "versus",
+                        &first.name,
+                        &second.name,
+                        &first_results,
+                    ));
+                    summaries.push(Summary::new(
+                        "versus",
+                        &second.name,
+                        &first.name,
+                        &second_results,
+                    ));
+                }
+            }
+            summaries
+        }
+    }
+}
+
+fn main() {
+    let options = parse_options();
+    let table = load_attack_table(&options.rules);
+    let summaries = run(&options, &table);
+    match options.format {
+        Format::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&summaries).expect("serializable summaries")
+            );
+        }
+        Format::Csv => {
+            println!("mode,bot,opponent,games,wins,losses,draws,pps,apm,lines,seconds");
+            for summary in &summaries {
+                println!(
+                    "{},{},{},{},{},{},{},{:.2},{:.2},{:.1},{:.1}",
+                    summary.mode,
+                    summary.bot,
+                    summary.opponent,
+                    summary.games,
+                    summary.wins,
+                    summary.losses,
+                    summary.draws,
+                    summary.pps,
+                    summary.apm,
+                    summary.lines,
+                    summary.seconds,
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::process::exit;
+
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use tetris::attack::AttackTable;
+use tetris::bot::BotWeights;
+use tetris::simulation::*;
+
+// 用遗传算法调整机器人的评估权重：每一代的个体在相同种子的马拉松对局中比较，
+// 保留最好的个体，其余由选出的父母交叉和变异产生
+// 每一代的种子不同，适应度不能跨代比较，保留的个体另外在一组固定的验证种子上评估，
+// 验证成绩超过之前所有代时才保存到文件，游戏中通过--bot-weights <名称>加载
+// 建议使用release构建：cargo run --release --bin tune -- --generations 20
+
+const USAGE: &str = "usage: tune [--population <n>] [--generations <n>] [--games <n>] [--seed <n>] \
+[--max-pieces <n>] [--depth <n>] [--mutation-rate <p>] [--output <file>]";
+
+// 权重的个数，与genes和from_genes一致
+const GENE_COUNT: usize = 10;
+// 初始种群和变异时随机改变的幅度
+const MUTATION_STEP: f32 = 0.2;
+// 选择父母时每次比较的个体数
+const TOURNAMENT_SIZE: usize = 3;
+// 直接保留到下一代的最好个体数
+const ELITE_COUNT: usize = 2;
+// 消行数之外，每行攻击在适应度中的权重
+const ATTACK_FITNESS: f32 = 2.0;
+
+#[derive(Debug)]
+struct Options {
+    population: usize,
+    generations: u32,
+    games: u64,
+    seed: u64,
+    max_pieces: u32,
+    depth: usize,
+    mutation_rate: f64,
+    output: String,
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("tune: {}\n{}", message, USAGE);
+    exit(2);
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: Option<String>) -> T {
+    value
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| usage_error(&format!("{} expects a number", name)))
+}
+
+fn parse_options() -> Options {
+    let mut options = Options {
+        population: 16,
+        generations: 10,
+        games: 4,
+        seed: 0,
+        max_pieces: 300,
+        depth: 1,
+        mutation_rate: 0.2,
+        output: "assets/bots/tuned.weights.ron".to_string(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--population" => options.population = parse_number(&arg, args.next()),
+            "--generations" => options.generations = parse_number(&arg, args.next()),
+            "--games" => options.games = parse_number(&arg, args.next()),
+            "--seed" => options.seed = parse_number(&arg, args.next()),
+            "--max-pieces" => options.max_pieces = parse_number(&arg, args.next()),
+            "--depth" => options.depth = parse_number(&arg, args.next()),
+            "--mutation-rate" => options.mutation_rate = parse_number(&arg, args.next()),
+            "--output" => {
+                options.output = args
+                    .next()
+                    .unwrap_or_else(|| usage_error("--output expects a file"));
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                exit(0);
+            }
+            _ => usage_error(&format!("unknown argument {:?}", arg)),
+        }
+    }
+    if options.population <= ELITE_COUNT || options.games == 0 || options.depth == 0 {
+        usage_error("population must exceed the elite count, games and depth must be positive");
+    }
+    options.mutation_rate = options.mutation_rate.clamp(0.0, 1.0);
+    options
+}
+
+fn genes(weights: &BotWeights) -> [f32; GENE_COUNT] {
+    let [single, double, triple, tetris] = weights.line_clears;
+    [
+        weights.aggregate_height,
+        weights.holes,
+        weights.bumpiness,
+        weights.wells,
+        weights.t_slots,
+        single,
+        double,
+        triple,
+        tetris,
+        weights.t_spin,
+    ]
+}
+
+fn from_genes(genes: [f32; GENE_COUNT]) -> BotWeights {
+    BotWeights {
+        aggregate_height: genes[0],
+        holes: genes[1],
+        bumpiness: genes[2],
+        wells: genes[3],
+        t_slots: genes[4],
+        line_clears: [genes[5], genes[6], genes[7], genes[8]],
+        t_spin: genes[9],
+    }
+}
+
+fn mutate(weights: &BotWeights, rate: f64, rng: &mut ChaCha8Rng) -> BotWeights {
+    from_genes(genes(weights).map(|gene| {
+        if rng.random_bool(rate) {
+            gene + rng.random_range(-MUTATION_STEP..=MUTATION_STEP)
+        } else {
+            gene
+        }
+    }))
+}
+
+// 每个权重随机取自父母之一
+fn crossover(first: &BotWeights, second: &BotWeights, rng: &mut ChaCha8Rng) -> BotWeights {
+    let (first, second) = (genes(first), genes(second));
+    from_genes(std::array::from_fn(|index| {
+        if rng.random_bool(0.5) {
+            first[index]
+        } else {
+            second[index]
+        }
+    }))
+}
+
+// 随机取几个个体，选出其中适应度最高的
+fn select<'a>(ranked: &'a [(BotWeights, f32)], rng: &mut ChaCha8Rng) -> &'a BotWeights {
+    let (weights, _) = (0..TOURNAMENT_SIZE)
+        .filter_map(|_| ranked.choose(rng))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("population is not empty");
+    weights
+}
+
+// 在同一组种子上的平均成绩：消行数加上攻击，堆到顶部的对局自然得分较低
+fn fitness(weights: &BotWeights, seeds: &[u64], options: &Options, table: &AttackTable) -> f32 {
+    let config = BotConfig {
+        name: "tune".to_string(),
+        pps: 1.0,
+        depth: options.depth,
+        mistake_rate: 0.0,
+        weights: *weights,
+    };
+    let total: f32 = seeds
+        .iter()
+        .map(|seed| {
+            let result = run_solo(
+                &config,
+                SoloGoal::Marathon,
+                *seed,
+                options.max_pieces,
+                table,
+            );
+            result.lines as f32 + ATTACK_FITNESS * result.attack as f32
+        })
+        .sum();
+    total / seeds.len() as f32
+}
+
+fn main() {
+    let options = parse_options();
+    let table = AttackTable::default();
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+    // 第一代为默认权重及其变异
+    let default_weights = BotWeights::default();
+    let mut population: Vec<BotWeights> = std::iter::once(default_weights)
+        .chain((1..options.population).map(|_| mutate(&default_weights, 1.0, &mut rng)))
+        .collect();
+    // 验证种子取训练种子之前的一段，与各代的训练种子不重叠
+    let validation_seeds: Vec<u64> = (1..=options.games)
+        .map(|game| options.seed.wrapping_sub(game))
+        .collect();
+    // 目前验证成绩最好的权重及其验证成绩
+    let mut best_validated: Option<(BotWeights, f32)> = None;
+    for generation in 0..options.generations {
+        // 每一代换一组种子，避免只适应少数骨牌序列
+        let seeds: Vec<u64> = (0..options.games)
+            .map(|game| {
+                options
+                    .seed
+                    .wrapping_add(generation as u64 * options.games + game)
+            })
+            .collect();
+        let mut ranked: Vec<(BotWeights, f32)> = population
+            .iter()
+            .copied()
+            .zip(parallel_map(&population, |weights| {
+                fitness(weights, &seeds, &options, &table)
+            }))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let mean = ranked.iter().map(|(_, fitness)| fitness).sum::<f32>() / ranked.len() as f32;
+        eprintln!(
+            "generation {}: best {:.2}, mean {:.2}",
+            generation + 1,
+            ranked[0].1,
+            mean
+        );
+
+        let elites: Vec<BotWeights> = ranked
+            .iter()
+            .take(ELITE_COUNT)
+            .map(|(weights, _)| *weights)
+            .collect();
+        let (elite, validation) = elites
+            .iter()
+            .copied()
+            .zip(parallel_map(&elites, |weights| {
+                fitness(weights, &validation_seeds, &options, &table)
+            }))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("elites are not empty");
+        if best_validated.is_none_or(|(_, best)| validation > best) {
+            best_validated = Some((elite, validation));
+            let comment = format!(
+                "tune: generation {}, validation fitness {:.2} over {} games of {} pieces",
+                generation + 1,
+                validation,
+                options.games,
+                options.max_pieces
+            );
+            if let Err(err) = save_weights(&options.output, &elite, &comment) {
+                eprintln!("tune: failed to save weights {}", err);
+                exit(1);
+            }
+            eprintln!(
+                "generation {}: validation {:.2}, saved",
+                generation + 1,
+                validation
+            );
+        }
+
+        population = elites;
+        while population.len() < options.population {
+            let child = crossover(
+                select(&ranked, &mut rng),
+                select(&ranked, &mut rng),
+                &mut rng,
+            );
+            population.push(mutate(&child, options.mutation_rate, &mut rng));
+        }
+    }
+    if let Some((weights, validation)) = best_validated {
+        println!("{:?}", weights);
+        eprintln!(
+            "tune: saved {} (validation fitness {:.2})",
+            options.output, validation
+        );
+    }
+}
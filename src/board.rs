@@ -1,168 +1,359 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::time::Duration;
 
 use bevy::prelude::*;
 
 use crate::common::*;
 use crate::piece::*;
 use crate::stats::*;
+use crate::timing::*;
 
-// game board宽高
-pub const COL_COUNT: u8 = 10;
-pub const ROW_COUNT: u8 = 20;
+// 标准game board宽高
+pub const STANDARD_COL_COUNT: i32 = 10;
+pub const STANDARD_ROW_COUNT: i32 = 20;
+// 可选的面板大小：标准、4宽、6宽、大面板
+pub const BOARD_SIZE_PRESETS: [BoardSize; 4] = [
+    BoardSize::new(STANDARD_COL_COUNT, STANDARD_ROW_COUNT),
+    BoardSize::new(4, 20),
+    BoardSize::new(6, 20),
+    BoardSize::new(20, 40),
+];
+// 面板最少列数，需容纳横置的I骨牌
+pub const MIN_COL_COUNT: i32 = 4;
 // 正方形方块边长
 pub const BLOCK_LENGTH: f32 = 30.0;
 // TODO 贴纸圆角
 // 正方形方块贴纸边长
 pub const BLOCK_STICKER_LENGTH: f32 = 28.0;
+// 面板最大显示长度，超出时缩小方块
+pub const BOARD_MAX_LENGTH: f32 = 600.0;
 
 // game board 边界厚度
 pub const BORDER_THICKNESS: f32 = 10.0;
 pub const BORDER_COLOR: Color = Color::srgb(0.8, 0.8, 0.8);
 
+// 垃圾行方块颜色
+pub const GARBAGE_COLOR: Color = Color::srgb(0.5, 0.5, 0.5);
+
+// game board宽高（方块数）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct BoardSize {
+    pub cols: i32,
+    pub rows: i32,
+    // 每个方块占据的格子边长，大方块模式下为2
+    pub cell_size: i32,
+}
+
+// 主菜单中选择的面板大小（格子数）
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct BoardSizeSetting(pub BoardSize);
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        BOARD_SIZE_PRESETS[0]
+    }
+}
+
+impl std::fmt::Display for BoardSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.cols, self.rows)
+    }
+}
+
+impl BoardSize {
+    pub const fn new(cols: i32, rows: i32) -> Self {
+        BoardSize {
+            cols,
+            rows,
+            cell_size: 1,
+        }
+    }
+
+    // 每个方块占据cell_size*cell_size个格子，方块数随之减少
+    // 面板过窄时减小cell_size，保证至少MIN_COL_COUNT列且不超出所选面板
+    pub fn with_cell_size(&self, cell_size: i32) -> Self {
+        let cell_size = cell_size.min(self.cols / MIN_COL_COUNT).max(1);
+        BoardSize {
+            cols: self.cols / cell_size,
+            rows: self.rows / cell_size,
+            cell_size,
+        }
+    }
+
+    // 格子显示边长，大面板时缩小以适应窗口
+    pub fn cell_length(&self) -> f32 {
+        BLOCK_LENGTH
+            .min(BOARD_MAX_LENGTH / (self.rows * self.cell_size) as f32)
+            .min(BOARD_MAX_LENGTH / (self.cols * self.cell_size) as f32)
+    }
+
+    // 方块显示边长
+    pub fn block_length(&self) -> f32 {
+        self.cell_length() * self.cell_size as f32
+    }
+
+    pub fn sticker_length(&self) -> f32 {
+        self.block_length() * BLOCK_STICKER_LENGTH / BLOCK_LENGTH
+    }
+
+    // 面板显示宽高（像素）
+    pub fn width(&self) -> f32 {
+        self.cols as f32 * self.block_length()
+    }
+
+    pub fn height(&self) -> f32 {
+        self.rows as f32 * self.block_length()
+    }
+
+    // 骨牌形状按标准10列面板定义，出生时水平居中并移到面板上方
+    pub fn spawn_offset(&self) -> (i32, i32) {
+        ((self.cols - STANDARD_COL_COUNT) / 2, self.rows)
+    }
+
+    // 下一个预设大小，循环切换
+    pub fn next_preset(&self) -> BoardSize {
+        let index = BOARD_SIZE_PRESETS
+            .iter()
+            .position(|preset| preset == self)
+            .map_or(0, |index| (index + 1) % BOARD_SIZE_PRESETS.len());
+        BOARD_SIZE_PRESETS[index]
+    }
+}
+
 // 方块
-#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Block {
     pub x: i32,
     pub y: i32,
 }
 
 impl Block {
-    pub fn translation(&self) -> Vec3 {
+    pub fn translation(&self, board_size: &BoardSize) -> Vec3 {
+        // 面板坐标系下的位置，镜像、翻转和旋转由BoardRoot的Transform处理
         // 方块xy原点为左下角
-        // 方块x范围0-9，方块y范围0-19
-        // 10*20个方块
+        // 方块x范围0到cols-1，方块y范围0到rows-1
+        let block_length = board_size.block_length();
         Vec3 {
-            x: (self.x as f32 - (COL_COUNT as f32 / 2.0) + 0.5) * BLOCK_LENGTH,
-            y: (self.y as f32 - (ROW_COUNT as f32 / 2.0) + 0.5) * BLOCK_LENGTH,
+            x: (self.x as f32 - (board_size.cols as f32 / 2.0) + 0.5) * block_length,
+            y: (self.y as f32 - (board_size.rows as f32 / 2.0) + 0.5) * block_length,
             z: 0.0,
         }
     }
 }
 
+// 方块锁定到面板上的时刻（本局游戏时长）
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LockedAt(pub Duration);
+
 impl From<[i32; 2]> for Block {
     fn from([x, y]: [i32; 2]) -> Self {
         Block { x, y }
     }
 }
 
-#[derive(Debug, Resource)]
-pub struct RemovePieceComponentTimer(pub Timer);
+// 从底部插入的垃圾行方块
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Garbage;
+
+// 骨牌锁定事件
+#[derive(Event, Debug, Clone)]
+pub struct PieceLocked {
+    pub piece_type: PieceType,
+    // 锁定位置
+    pub blocks: Vec<Block>,
+    pub t_spin: bool,
+}
+
+// 消行事件
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LinesCleared {
+    pub count: u32,
+    // 消行后面板是否全空
+    pub perfect_clear: bool,
+    pub t_spin: bool,
+    // 连锁序号，骨牌锁定直接引起的消行为0，之后每次连锁消行加一
+    pub chain: u32,
+}
+
+// 检查方块是否超出边界或与面板方块重叠
+pub fn is_overlapping(piece: &[Block], board: &[Block], board_size: &BoardSize) -> bool {
+    piece.iter().any(|block| {
+        block.x < 0
+            || block.x >= board_size.cols
+            || block.y < 0
+            || board
+                .iter()
+                .any(|board_block| board_block.x == block.x && board_block.y == block.y)
+    })
+}
 
-pub fn setup_game_board(mut commands: Commands) {
+// 骨牌可以直接下落的格数
+pub fn drop_distance(piece: &[Block], board: &[Block], board_size: &BoardSize) -> i32 {
+    let mut distance = 0;
+    loop {
+        let shifted: Vec<Block> = piece
+            .iter()
+            .map(|block| Block {
+                x: block.x,
+                y: block.y - distance - 1,
+            })
+            .collect();
+        if is_overlapping(&shifted, board, board_size) {
+            return distance;
+        }
+        distance += 1;
+    }
+}
+
+// game board边界
+#[derive(Debug, Clone, Copy, Component)]
+pub enum BoardBorder {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl BoardBorder {
     // 三维坐标原点在board中央
-    // 左侧边界
-    let half_col_count = COL_COUNT as f32 / 2.0;
-    let half_raw_count = ROW_COUNT as f32 / 2.0;
-    commands.spawn((
+    pub fn transform(&self, board_size: &BoardSize) -> Transform {
+        let half_width = board_size.width() / 2.0;
+        let half_height = board_size.height() / 2.0;
+        let (translation, scale) = match self {
+            BoardBorder::Left => (
+                Vec3::new(-half_width - BORDER_THICKNESS / 2.0, 0.0, 0.0),
+                Vec3::new(
+                    BORDER_THICKNESS,
+                    board_size.height() + 2.0 * BORDER_THICKNESS,
+                    0.0,
+                ),
+            ),
+            BoardBorder::Right => (
+                Vec3::new(half_width + BORDER_THICKNESS / 2.0, 0.0, 0.0),
+                Vec3::new(
+                    BORDER_THICKNESS,
+                    board_size.height() + 2.0 * BORDER_THICKNESS,
+                    0.0,
+                ),
+            ),
+            BoardBorder::Top => (
+                Vec3::new(0.0, half_height + BORDER_THICKNESS / 2.0, 0.0),
+                Vec3::new(board_size.width(), BORDER_THICKNESS, 0.0),
+            ),
+            BoardBorder::Bottom => (
+                Vec3::new(0.0, -half_height - BORDER_THICKNESS / 2.0, 0.0),
+                Vec3::new(board_size.width(), BORDER_THICKNESS, 0.0),
+            ),
+        };
         Transform {
-            translation: Vec3 {
-                x: -half_col_count * BLOCK_LENGTH - BORDER_THICKNESS / 2.0,
-                ..default()
-            },
-            scale: Vec3 {
-                x: BORDER_THICKNESS,
-                y: ROW_COUNT as f32 * BLOCK_LENGTH + 2.0 * BORDER_THICKNESS,
-                z: 0.0,
-            },
-            ..default()
-        },
-        Sprite {
-            color: BORDER_COLOR,
+            translation,
+            scale,
             ..default()
-        },
-    ));
+        }
+    }
+}
 
-    // 右侧边界
-    commands.spawn((
-        Transform {
-            translation: Vec3 {
-                x: half_col_count * BLOCK_LENGTH + BORDER_THICKNESS / 2.0,
-                ..default()
-            },
-            scale: Vec3 {
-                x: BORDER_THICKNESS,
-                y: ROW_COUNT as f32 * BLOCK_LENGTH + 2.0 * BORDER_THICKNESS,
-                z: 0.0,
-            },
-            ..default()
-        },
-        Sprite {
-            color: BORDER_COLOR,
-            ..default()
-        },
-    ));
+// 根据游戏模式和菜单设置确定实际面板大小
+pub fn apply_board_size(
+    game_mode: Res<GameMode>,
+    board_size_setting: Res<BoardSizeSetting>,
+    mut board_size: ResMut<BoardSize>,
+) {
+    let new_board_size = match *game_mode {
+        // 定式和谜题按标准面板设计，对战使用标准面板，每日挑战所有玩家使用相同面板
+        GameMode::Opener
+        | GameMode::Puzzle
+        | GameMode::Versus
+        | GameMode::VersusCpu
+        | GameMode::Daily => BoardSize::default(),
+        GameMode::Big => board_size_setting.0.with_cell_size(2),
+        _ => board_size_setting.0,
+    };
+    if *board_size != new_board_size {
+        info!("board size: {:?}", new_board_size);
+        *board_size = new_board_size;
+    }
+}
 
-    // 上侧边界
-    commands.spawn((
-        Transform {
-            translation: Vec3 {
-                y: half_raw_count * BLOCK_LENGTH + BORDER_THICKNESS / 2.0,
+pub fn setup_game_board(mut commands: Commands, board_size: Res<BoardSize>) {
+    for border in [
+        BoardBorder::Left,
+        BoardBorder::Right,
+        BoardBorder::Top,
+        BoardBorder::Bottom,
+    ] {
+        commands.spawn((
+            border.transform(&board_size),
+            Sprite {
+                color: BORDER_COLOR,
                 ..default()
             },
-            scale: Vec3 {
-                x: COL_COUNT as f32 * BLOCK_LENGTH,
-                y: BORDER_THICKNESS,
-                z: 0.0,
-            },
-            ..default()
-        },
-        Sprite {
-            color: BORDER_COLOR,
-            ..default()
-        },
-    ));
+            border,
+        ));
+    }
+}
 
-    // 下侧边界
-    commands.spawn((
-        Transform {
-            translation: Vec3 {
-                y: -half_raw_count * BLOCK_LENGTH - BORDER_THICKNESS / 2.0,
-                ..default()
-            },
-            scale: Vec3 {
-                x: COL_COUNT as f32 * BLOCK_LENGTH,
-                y: BORDER_THICKNESS,
-                z: 0.0,
-            },
-            ..default()
-        },
-        Sprite {
-            color: BORDER_COLOR,
-            ..default()
-        },
-    ));
+// 面板大小改变时调整边界
+pub fn update_game_board(
+    board_size: Res<BoardSize>,
+    mut q_border: Query<(&BoardBorder, &mut Transform)>,
+) {
+    for (border, mut transform) in &mut q_border {
+        *transform = border.transform(&board_size);
+    }
 }
 
 // 当piece移到底部后，移除piece组件
 pub fn remove_piece_component(
     mut commands: Commands,
-    q_piece_blocks: Query<(Entity, &Movable), With<PieceType>>,
-    mut timer: ResMut<RemovePieceComponentTimer>,
+    q_piece_blocks: Query<(Entity, &Block, &PieceType, &Movable)>,
+    q_board: Query<&Block, Without<PieceType>>,
+    board_size: Res<BoardSize>,
+    last_move_is_rotation: Res<LastMoveIsRotation>,
+    mut timers: ResMut<GameTimers>,
+    mut piece_locked_events: EventWriter<PieceLocked>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
     time: Res<Time>,
 ) {
-    if !q_piece_blocks.is_empty() && !q_piece_blocks.iter().last().unwrap().1.can_down {
-        if !q_piece_blocks.iter().last().unwrap().1.can_down {
-            timer.0.tick(time.delta());
-        } else {
-            // 无法下移时，通过左右移动获得重新下移能力
-            timer.0.reset();
-        }
+    let Some((_, _, piece_type, movable)) = q_piece_blocks.iter().next() else {
+        return;
+    };
+    if movable.can_down {
+        // 通过左右移动获得重新下移能力时，重置锁定延迟
+        timers.lock.reset();
+        return;
     }
-    let mut reset_timer = false;
-    for (entity, movable) in &q_piece_blocks {
-        // 到达底部后，仍可短时间内左右移动
-        if !movable.can_down {
-            // 当到达底部后，按向下键时，跳过timer直接开始新一个piece
-            if timer.0.just_finished() || keyboard_input.pressed(KeyCode::ArrowDown) {
-                commands.entity(entity).remove::<PieceType>();
-                reset_timer = true;
-            }
+    // 到达底部后，仍可在锁定延迟内左右移动
+    timers.lock.tick(time.delta());
+    // 当到达底部后，按向下键时，跳过锁定延迟直接开始新一个piece
+    if timers.lock.finished() || keyboard_input.pressed(KeyCode::ArrowDown) {
+        let piece: Vec<Block> = q_piece_blocks
+            .iter()
+            .map(|(_, block, _, _)| *block)
+            .collect();
+        let board: Vec<Block> = q_board.iter().copied().collect();
+        let t_spin = is_lock_t_spin(
+            *piece_type,
+            last_move_is_rotation.0,
+            &piece,
+            &board,
+            &board_size,
+        );
+        for (entity, _, _, _) in &q_piece_blocks {
+            commands
+                .entity(entity)
+                .remove::<PieceType>()
+                .insert(LockedAt(timers.elapsed));
         }
-    }
-    if reset_timer {
-        timer.0.reset();
+        timers.lock.reset();
+        timers.are.reset();
+        finish_timer(&mut timers.line_clear);
+        piece_locked_events.write(PieceLocked {
+            piece_type: *piece_type,
+            blocks: piece,
+            t_spin,
+        });
     }
 }
 
@@ -170,87 +361,304 @@ pub fn remove_piece_component(
 pub fn check_full_line(
     mut commands: Commands,
     game_audios: Res<GameAudios>,
-    mut score: ResMut<Score>,
+    game_mode: Res<GameMode>,
     mut lines: ResMut<Lines>,
+    board_size: Res<BoardSize>,
+    mut timers: ResMut<GameTimers>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut lines_cleared_events: EventWriter<LinesCleared>,
     mut query: Query<(Entity, &mut Block, &mut Transform), Without<PieceType>>,
 ) {
-    let mut y_to_x_set_map: HashMap<i32, HashSet<i32>> = HashMap::new();
-    for (_, block, _) in &query {
-        if y_to_x_set_map.contains_key(&block.y) {
-            let x_set = y_to_x_set_map.get_mut(&block.y).unwrap();
-            x_set.insert(block.x);
-        } else {
-            let mut x_set = HashSet::new();
-            x_set.insert(block.x);
-            y_to_x_set_map.insert(block.y, x_set);
+    // 本帧锁定的骨牌是否为T-spin
+    let t_spin = piece_locked_events.read().any(|event| event.t_spin);
+    let mut blocks: Vec<(Entity, Block)> = query
+        .iter()
+        .map(|(entity, block, _)| (entity, *block))
+        .collect();
+    let all_entities: Vec<Entity> = blocks.iter().map(|(entity, _)| *entity).collect();
+    let cleared = clear_full_lines(
+        &mut blocks,
+        &board_size,
+        *game_mode == GameMode::Cascade,
+        t_spin,
+    );
+    if cleared.is_empty() {
+        return;
+    }
+    for lines_cleared in cleared {
+        // 行数增加
+        lines.0 += lines_cleared.count;
+        lines_cleared_events.write(lines_cleared);
+    }
+    // 消除行
+    let remaining: HashSet<Entity> = blocks.iter().map(|(entity, _)| *entity).collect();
+    for entity in all_entities {
+        if !remaining.contains(&entity) {
+            commands.entity(entity).despawn();
         }
     }
-    let mut full_lines = Vec::new();
-    for (y, x_set) in y_to_x_set_map.iter() {
-        if x_set.len() == COL_COUNT as usize {
-            full_lines.push(y);
+    commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+    // 消行延迟
+    timers.line_clear.reset();
+    for (entity, new_block) in blocks {
+        if let Ok((_, mut block, mut transform)) = query.get_mut(entity)
+            && *block != new_block
+        {
+            info!("down block: {:?} -> {:?}", block, new_block);
+            *block = new_block;
+            transform.translation = block.translation(&board_size);
         }
     }
-    if full_lines.len() > 0 {
-        dbg!(full_lines.len());
-        commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
-    }
-    // 行数增加
-    lines.0 += full_lines.len() as u32;
-    // 分数增加
-    score.0 += match full_lines.len() {
-        0 => 0,
-        1 => 100,
-        2 => 200,
-        3 => 400,
-        4 => 800,
-        _ => 1000,
-    };
+}
 
-    // 消除行
-    let mut despawn_entities = Vec::new();
-    for line_no in full_lines.iter() {
-        let line_no = **line_no;
-        for (entity, block, _) in &mut query {
-            if block.y == line_no {
-                despawn_entities.push(entity);
-                commands.entity(entity).despawn();
-            }
+// 消除已满的行，返回每次消行
+// 连锁重力模式下不相连的方块块各自下落，下落后可能再次消行
+pub fn clear_full_lines<T>(
+    blocks: &mut Vec<(T, Block)>,
+    board_size: &BoardSize,
+    cascade: bool,
+    t_spin: bool,
+) -> Vec<LinesCleared> {
+    let mut cleared = Vec::new();
+    loop {
+        let mut full_lines = find_full_lines(blocks, board_size);
+        if full_lines.is_empty() {
+            break;
+        }
+        let chain = cleared.len() as u32;
+        cleared.push(LinesCleared {
+            count: full_lines.len() as u32,
+            perfect_clear: blocks.len() == full_lines.len() * board_size.cols as usize,
+            t_spin: t_spin && chain == 0,
+            chain,
+        });
+        blocks.retain(|(_, block)| !full_lines.contains(&block.y));
+        if cascade {
+            drop_floating_chunks(blocks);
+        } else {
+            collapse_lines(blocks, &mut full_lines);
+            break;
         }
     }
-    // 消除行的上面block整体向下移
+    cleared
+}
+
+// 已满的行
+pub fn find_full_lines<T>(blocks: &[(T, Block)], board_size: &BoardSize) -> Vec<i32> {
+    let mut y_to_x_set_map: HashMap<i32, HashSet<i32>> = HashMap::new();
+    for (_, block) in blocks {
+        y_to_x_set_map.entry(block.y).or_default().insert(block.x);
+    }
+    y_to_x_set_map
+        .into_iter()
+        .filter(|(_, x_set)| x_set.len() == board_size.cols as usize)
+        .map(|(y, _)| y)
+        .collect()
+}
+
+// 消除行的上面block整体向下移，full_lines按从高到低排序
+pub fn collapse_lines<T>(blocks: &mut [(T, Block)], full_lines: &mut [i32]) {
     full_lines.sort();
     full_lines.reverse();
     for line_no in full_lines.iter() {
-        for (entity, mut block, mut transform) in &mut query {
-            if !despawn_entities.contains(&entity) && block.y > **line_no {
-                info!("down block: {:?}, line_no: {}", block, line_no);
+        for (_, block) in blocks.iter_mut() {
+            if block.y > *line_no {
                 block.y -= 1;
-                transform.translation = block.translation();
             }
         }
     }
 }
 
+// 按上下左右相连划分方块块，悬空的方块块整体下落到底，直到没有方块块可以下落
+pub fn drop_floating_chunks<T>(blocks: &mut [(T, Block)]) {
+    loop {
+        let mut moved = false;
+        let mut chunks = connected_chunks(blocks);
+        // 从下往上处理，下方的方块块先落下
+        chunks.sort_by_key(|chunk| chunk.iter().map(|index| blocks[*index].1.y).min());
+        for chunk in chunks {
+            let others: HashSet<(i32, i32)> = blocks
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !chunk.contains(index))
+                .map(|(_, (_, block))| (block.x, block.y))
+                .collect();
+            let mut distance = 0;
+            while chunk.iter().all(|index| {
+                let block = blocks[*index].1;
+                block.y - distance > 0 && !others.contains(&(block.x, block.y - distance - 1))
+            }) {
+                distance += 1;
+            }
+            if distance > 0 {
+                for index in &chunk {
+                    blocks[*index].1.y -= distance;
+                }
+                moved = true;
+            }
+        }
+        if !moved {
+            return;
+        }
+    }
+}
+
+// 相连的方块块，返回每块中方块的序号
+fn connected_chunks<T>(blocks: &[(T, Block)]) -> Vec<Vec<usize>> {
+    let positions: HashMap<(i32, i32), usize> = blocks
+        .iter()
+        .enumerate()
+        .map(|(index, (_, block))| ((block.x, block.y), index))
+        .collect();
+    let mut visited = vec![false; blocks.len()];
+    let mut chunks = Vec::new();
+    for start in 0..blocks.len() {
+        if visited[start] {
+            continue;
+        }
+        visited[start] = true;
+        let mut chunk = vec![start];
+        let mut next = 0;
+        while next < chunk.len() {
+            let block = blocks[chunk[next]].1;
+            next += 1;
+            for (delta_x, delta_y) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                if let Some(index) = positions.get(&(block.x + delta_x, block.y + delta_y))
+                    && !visited[*index]
+                {
+                    visited[*index] = true;
+                    chunk.push(*index);
+                }
+            }
+        }
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+// 方块堆到出生行时游戏结束
+pub fn is_topped_out<'a>(
+    blocks: impl IntoIterator<Item = &'a Block>,
+    board_size: &BoardSize,
+) -> bool {
+    blocks
+        .into_iter()
+        .any(|block| block.y >= board_size.rows - 1)
+}
+
 // 检查是否游戏结束
 pub fn check_game_over(
     mut commands: Commands,
     game_audios: Res<GameAudios>,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
-    query: Query<&Block, Without<PieceType>>,
+    query: Query<(Entity, &Block), Without<PieceType>>,
 ) {
-    let mut max_block_y = 0;
-    for block in &query {
-        if block.y > max_block_y {
-            max_block_y = block.y;
+    if !is_topped_out(query.iter().map(|(_, block)| block), &board_size) {
+        return;
+    }
+    match *game_mode {
+        // 禅模式不会结束，清除上半部分面板后继续
+        GameMode::Zen => {
+            info!("zen mode topped out, clearing top rows");
+            commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+            for (entity, block) in &query {
+                if block.y >= board_size.rows / 2 {
+                    commands.entity(entity).despawn();
+                }
+            }
+        }
+        _ => {
+            commands.spawn(AudioPlayer(game_audios.gameover.clone()));
+            app_state.set(AppState::GameOver);
+            game_state.set(GameState::GameQuited);
         }
     }
-    // info!("max_block_y: {}", max_block_y);
-    if max_block_y >= 19 {
-        commands.spawn(AudioPlayer(game_audios.gameover.clone()));
-        app_state.set(AppState::GameOver);
-        game_state.set(GameState::GameQuited);
+}
+
+// 生成已固定在面板上的方块
+pub fn spawn_board_block(
+    commands: &mut Commands,
+    block: Block,
+    color: Color,
+    board_size: &BoardSize,
+) -> Entity {
+    commands
+        .spawn(new_block_sprite(
+            &block,
+            color,
+            Visibility::Visible,
+            board_size,
+        ))
+        .insert(block)
+        .id()
+}
+
+// 从底部插入垃圾行，面板方块整体上移
+// holes[i]为第i行（自下而上）空缺的x坐标
+pub fn insert_garbage_rows(
+    commands: &mut Commands,
+    holes: &[i32],
+    board_size: &BoardSize,
+    q_board: &mut Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    q_piece: &mut Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    let blocks = garbage_row_blocks(holes, board_size.cols);
+    insert_garbage_blocks(
+        commands,
+        holes.len() as i32,
+        &blocks,
+        board_size,
+        q_board,
+        q_piece,
+    );
+}
+
+// 底部垃圾行的方块，holes[i]为第i行（自下而上）空缺的x坐标
+pub fn garbage_row_blocks(holes: &[i32], cols: i32) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    for (y, hole) in holes.iter().enumerate() {
+        for x in (0..cols).filter(|x| x != hole) {
+            blocks.push(Block { x, y: y as i32 });
+        }
+    }
+    blocks
+}
+
+// 面板方块整体上移rows行，在底部空出的位置生成垃圾方块
+pub fn insert_garbage_blocks(
+    commands: &mut Commands,
+    rows: i32,
+    blocks: &[Block],
+    board_size: &BoardSize,
+    q_board: &mut Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    q_piece: &mut Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    if rows == 0 {
+        return;
+    }
+    let mut occupied = HashSet::new();
+    for (mut block, mut transform) in q_board.iter_mut() {
+        block.y += rows;
+        transform.translation = block.translation(board_size);
+        occupied.insert((block.x, block.y));
+    }
+    for block in blocks {
+        occupied.insert((block.x, block.y));
+        let entity = spawn_board_block(commands, *block, GARBAGE_COLOR, board_size);
+        commands.entity(entity).insert(Garbage);
+    }
+    // 当前骨牌与面板方块重叠时，随面板一起上移
+    if q_piece
+        .iter()
+        .any(|(block, _)| occupied.contains(&(block.x, block.y)))
+    {
+        for (mut block, mut transform) in q_piece.iter_mut() {
+            block.y += rows;
+            transform.translation = block.translation(board_size);
+        }
     }
 }
 
@@ -259,3 +667,102 @@ pub fn clear_game_board(mut commands: Commands, query: Query<Entity, With<Block>
         commands.entity(entity).despawn();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blocks(cells: &[(i32, i32)]) -> Vec<(Entity, Block)> {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(index, (x, y))| (Entity::from_raw(index as u32), Block { x: *x, y: *y }))
+            .collect()
+    }
+
+    fn cells(blocks: &[(Entity, Block)]) -> Vec<(i32, i32)> {
+        let mut cells: Vec<(i32, i32)> =
+            blocks.iter().map(|(_, block)| (block.x, block.y)).collect();
+        cells.sort();
+        cells
+    }
+
+    #[test]
+    fn big_cells_fit_inside_selected_board() {
+        for board_size in BOARD_SIZE_PRESETS {
+            let big = board_size.with_cell_size(2);
+            assert!(big.cols >= MIN_COL_COUNT);
+            assert!(big.cols * big.cell_size <= board_size.cols);
+            assert!(big.rows * big.cell_size <= board_size.rows);
+        }
+        assert_eq!(BoardSize::new(10, 20).with_cell_size(2).cell_size, 2);
+        assert_eq!(BoardSize::new(6, 20).with_cell_size(2).cell_size, 1);
+    }
+
+    #[test]
+    fn connected_chunks_splits_by_adjacency() {
+        // 斜向相邻不算相连
+        let blocks = blocks(&[(0, 0), (1, 0), (1, 1), (3, 0), (2, 2)]);
+        let mut chunks: Vec<Vec<usize>> = connected_chunks(&blocks)
+            .into_iter()
+            .map(|mut chunk| {
+                chunk.sort();
+                chunk
+            })
+            .collect();
+        chunks.sort();
+        assert_eq!(chunks, vec![vec![0, 1, 2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn floating_chunks_fall_onto_stack() {
+        let mut blocks = blocks(&[(0, 0), (0, 3), (1, 3), (1, 4), (3, 5)]);
+        drop_floating_chunks(&mut blocks);
+        assert_eq!(cells(&blocks), vec![(0, 0), (0, 1), (1, 1), (1, 2), (3, 0)]);
+    }
+
+    #[test]
+    fn cascade_chunk_triggers_chain_clear() {
+        let board_size = BoardSize::new(4, 20);
+        // 底行消除后，上方两块分别落下，拼成新的满行
+        let mut blocks = blocks(&[
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (3, 3),
+        ]);
+        let cleared = clear_full_lines(&mut blocks, &board_size, true, true);
+        assert_eq!(cleared.len(), 2);
+        assert_eq!(cleared[0].chain, 0);
+        assert!(cleared[0].t_spin);
+        assert!(!cleared[0].perfect_clear);
+        assert_eq!(cleared[1].chain, 1);
+        assert_eq!(cleared[1].count, 1);
+        assert!(!cleared[1].t_spin);
+        assert!(cleared[1].perfect_clear);
+        assert!(blocks.is_empty());
+    }
+
+    #[test]
+    fn normal_clear_collapses_without_chain() {
+        let board_size = BoardSize::new(4, 20);
+        let mut blocks = blocks(&[
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (1, 1),
+            (2, 1),
+            (3, 3),
+        ]);
+        let cleared = clear_full_lines(&mut blocks, &board_size, false, false);
+        assert_eq!(cleared.len(), 1);
+        assert_eq!(cleared[0].count, 1);
+        assert_eq!(cells(&blocks), vec![(0, 0), (1, 0), (2, 0), (3, 2)]);
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::{HashSet, VecDeque};
+
+use bevy::prelude::{Asset, TypePath};
+use serde::{Deserialize, Serialize};
+
+use crate::board::*;
+use crate::engine::{ActivePiece, Game};
+use crate::piece::*;
+use crate::piece_set::{ActivePieceSet, rotate_blocks};
+
+// 不依赖ECS的落点搜索，可用于自动游玩、落点提示和电脑对手
+// 游戏没有暂存功能，只考虑当前骨牌和预览中的骨牌
+
+// 搜索时每层保留的候选落点数
+const BEAM_WIDTH: usize = 5;
+// 堆到顶部的评分
+const TOPPED_OUT_SCORE: f32 = -1.0e9;
+
+// 电脑操作骨牌的输入
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BotInput {
+    Left,
+    Right,
+    Rotate,
+    // 直接下落到底但不锁定，之后仍可移动或旋转
+    SoftDrop,
+    HardDrop,
+}
+
+// 面板评估的权重，可以从assets/bots/*.weights.ron加载
+#[derive(Asset, TypePath, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BotWeights {
+    // 各列高度之和
+    pub aggregate_height: f32,
+    // 上方有方块的空格数
+    pub holes: f32,
+    // 相邻列高度差之和
+    pub bumpiness: f32,
+    // 比两侧都低的列的深度之和
+    pub wells: f32,
+    // 可以放入T骨牌做T-spin的空位数
+    pub t_slots: f32,
+    // 一次消1~4行的奖励
+    pub line_clears: [f32; 4],
+    // T-spin消行每行的额外奖励
+    pub t_spin: f32,
+}
+
+impl Default for BotWeights {
+    fn default() -> Self {
+        BotWeights {
+            aggregate_height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.1,
+            t_slots: 0.3,
+            line_clears: [0.76, 1.52, 2.28, 4.0],
+            t_spin: 1.0,
+        }
+    }
+}
+
+// 当前骨牌的一个最终落点及到达它的输入，最后一个输入为硬降
+#[derive(Debug, Clone)]
+pub struct Placement {
+    pub blocks: Vec<Block>,
+    // 落点的旋转状态
+    pub state: usize,
+    pub inputs: Vec<BotInput>,
+    pub t_spin: bool,
+}
+
+// 执行一个输入，返回是否成功
+pub fn apply_input(game: &mut Game, input: BotInput) -> bool {
+    match input {
+        BotInput::Left => game.move_piece(-1),
+        BotInput::Right => game.move_piece(1),
+        BotInput::Rotate => game.rotate_piece(),
+        BotInput::SoftDrop => game.drop_piece(game.drop_distance()) > 0,
+        BotInput::HardDrop => game.hard_drop().is_some(),
+    }
+}
+
+// 搜索中的骨牌状态，rotated为最后一个输入是否为旋转
+#[derive(Debug, Clone)]
+struct SearchNode {
+    piece: ActivePiece,
+    rotated: bool,
+    inputs: Vec<BotInput>,
+}
+
+fn sorted_blocks(blocks: &[Block]) -> Vec<Block> {
+    let mut blocks = blocks.to_vec();
+    blocks.sort_by_key(|block| (block.y, block.x));
+    blocks
+}
+
+// 在面板上执行一个输入，不能执行时返回None
+fn step(
+    node: &SearchNode,
+    input: BotInput,
+    board: &[Block],
+    board_size: &BoardSize,
+) -> Option<SearchNode> {
+    let piece = &node.piece;
+    let (blocks, state) = match input {
+        BotInput::Left | BotInput::Right => {
+            let delta_x = if input == BotInput::Left { -1 } else { 1 };
+            let shifted = shift_piece(&piece.blocks, Some(delta_x), None);
+            if is_overlapping(&shifted, board, board_size) {
+                return None;
+            }
+            (shifted, piece.state)
+        }
+        BotInput::Rotate => rotate_blocks(
+            piece.piece_type,
+            piece.state,
+            &piece.blocks,
+            board,
+            board_size,
+            &ActivePieceSet::default(),
+        )?,
+        BotInput::SoftDrop => {
+            let distance = drop_distance(&piece.blocks, board, board_size);
+            if distance == 0 {
+                return None;
+            }
+            (
+                shift_piece(&piece.blocks, None, Some(-distance)),
+                piece.state,
+            )
+        }
+        BotInput::HardDrop => return None,
+    };
+    let mut inputs = node.inputs.clone();
+    inputs.push(input);
+    Some(SearchNode {
+        piece: ActivePiece {
+            piece_type: piece.piece_type,
+            blocks,
+            state,
+        },
+        rotated: input == BotInput::Rotate,
+        inputs,
+    })
+}
+
+// 从当前位置出发，通过移动、旋转和下落可以到达的所有最终落点，包括软降后的横移和旋转
+// 使用广度优先搜索，每个落点保留输入最少的路径
+pub fn placements(game: &Game) -> Vec<Placement> {
+    let Some(piece) = &game.piece else {
+        return Vec::new();
+    };
+    let board = game.board_blocks();
+    let board_size = &game.board_size;
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::from([SearchNode {
+        piece: piece.clone(),
+        rotated: false,
+        inputs: Vec::new(),
+    }]);
+    let mut placements: Vec<Placement> = Vec::new();
+    while let Some(node) = queue.pop_front() {
+        if !visited.insert((
+            sorted_blocks(&node.piece.blocks),
+            node.piece.state,
+            node.rotated,
+        )) {
+            continue;
+        }
+        if drop_distance(&node.piece.blocks, &board, board_size) == 0 {
+            let blocks = sorted_blocks(&node.piece.blocks);
+            let t_spin = is_lock_t_spin(
+                node.piece.piece_type,
+                node.rotated,
+                &blocks,
+                &board,
+                board_size,
+            );
+            if !placements
+                .iter()
+                .any(|placement| placement.blocks == blocks && placement.t_spin == t_spin)
+            {
+                let mut inputs = node.inputs.clone();
+                inputs.push(BotInput::HardDrop);
+                placements.push(Placement {
+                    blocks,
+                    state: node.piece.state,
+                    inputs,
+                    t_spin,
+                });
+            }
+        }
+        for input in [
+            BotInput::Left,
+            BotInput::Right,
+            BotInput::Rotate,
+            BotInput::SoftDrop,
+        ] {
+            if let Some(next) = step(&node, input, &board, board_size) {
+                queue.push_back(next);
+            }
+        }
+    }
+    placements
+}
+
+// 按输入放置当前骨牌后的对局，以及锁定时的消行
+pub fn simulate(game: &Game, placement: &Placement) -> (Game, Option<LinesCleared>) {
+    let mut game = game.clone();
+    let mut lines_cleared = None;
+    for input in &placement.inputs {
+        if *input == BotInput::HardDrop {
+            lines_cleared = game.hard_drop();
+        } else {
+            apply_input(&mut game, *input);
+        }
+    }
+    (game, lines_cleared)
+}
+
+// 面板的占用情况，左右和下方边界外视为已占用
+struct Field {
+    cols: i32,
+    rows: i32,
+    cells: Vec<bool>,
+}
+
+impl Field {
+    fn new(game: &Game) -> Self {
+        let cols = game.board_size.cols;
+        let rows = game.board_size.rows;
+        let mut cells = vec![false; (cols * rows) as usize];
+        for (_, block) in &game.board {
+            if block.x >= 0 && block.x < cols && block.y >= 0 && block.y < rows {
+                cells[(block.y * cols + block.x) as usize] = true;
+            }
+        }
+        Field { cols, rows, cells }
+    }
+
+    fn filled(&self, x: i32, y: i32) -> bool {
+        if x < 0 || x >= self.cols || y < 0 {
+            return true;
+        }
+        y < self.rows && self.cells[(y * self.cols + x) as usize]
+    }
+
+    fn heights(&self) -> Vec<i32> {
+        (0..self.cols)
+            .map(|x| {
+                (0..self.rows)
+                    .rev()
+                    .find(|y| self.filled(x, *y))
+                    .map_or(0, |y| y + 1)
+            })
+            .collect()
+    }
+
+    // 朝下的T骨牌可以放入且不会继续下落，中心的四个角至少三个被占用
+    fn is_t_slot(&self, x: i32, y: i32) -> bool {
+        let cells = [(x - 1, y), (x, y), (x + 1, y), (x, y - 1)];
+        if cells.iter().any(|(x, y)| self.filled(*x, *y)) || !self.filled(x, y - 2) {
+            return false;
+        }
+        [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+            .iter()
+            .filter(|(dx, dy)| self.filled(x + dx, y + dy))
+            .count()
+            >= 3
+    }
+}
+
+// 消行的奖励
+pub fn clear_score(lines_cleared: &LinesCleared, weights: &BotWeights) -> f32 {
+    let count = lines_cleared.count as usize;
+    if count == 0 {
+        return 0.0;
+    }
+    let mut score = weights.line_clears[count.min(weights.line_clears.len()) - 1];
+    if lines_cleared.t_spin {
+        score += weights.t_spin * count as f32;
+    }
+    score
+}
+
+// 面板评分，不包括消行奖励
+pub fn evaluate(game: &Game, weights: &BotWeights) -> f32 {
+    if game.topped_out {
+        return TOPPED_OUT_SCORE;
+    }
+    let field = Field::new(game);
+    let heights = field.heights();
+    let aggregate_height: i32 = heights.iter().sum();
+    let bumpiness: i32 = heights
+        .windows(2)
+        .map(|pair| (pair[0] - pair[1]).abs())
+        .sum();
+    let mut holes = 0;
+    let mut wells = 0;
+    let mut t_slots = 0;
+    for x in 0..field.cols {
+        let height = heights[x as usize];
+        holes += (0..height).filter(|y| !field.filled(x, *y)).count();
+        // 面板外视为与面板一样高
+        let left = if x > 0 {
+            heights[x as usize - 1]
+        } else {
+            field.rows
+        };
+        let right = heights.get(x as usize + 1).copied().unwrap_or(field.rows);
+        wells += (left.min(right) - height).max(0);
+        // 槽位可能在两侧的悬空方块下方，检查到相邻列的高度
+        let neighbours = (x - 1).max(0) as usize..=(x + 1).min(field.cols - 1) as usize;
+        let top = heights[neighbours].iter().copied().max().unwrap_or(height);
+        t_slots += (1..=top.min(field.rows - 1))
+            .filter(|y| field.is_t_slot(x, *y))
+            .count();
+    }
+    weights.aggregate_height * aggregate_height as f32
+        + weights.holes * holes as f32
+        + weights.bumpiness * bumpiness as f32
+        + weights.wells * wells as f32
+        + weights.t_slots * t_slots as f32
+}
+
+// 当前骨牌的所有落点及放置后的对局、累计消行奖励和评分，按评分从高到低排序
+fn candidates(game: &Game, reward: f32, weights: &BotWeights) -> Vec<(Placement, Game, f32, f32)> {
+    let mut candidates: Vec<_> = placements(game)
+        .into_iter()
+        .map(|placement| {
+            let (after, lines_cleared) = simulate(game, &placement);
+            let reward = reward
+                + lines_cleared.map_or(0.0, |lines_cleared| clear_score(&lines_cleared, weights));
+            let score = evaluate(&after, weights) + reward;
+            (placement, after, reward, score)
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.3.total_cmp(&a.3));
+    candidates
+}
+
+// 取出预览中的下一个骨牌继续搜索，返回能达到的最高评分
+fn search(game: &Game, depth: usize, reward: f32, weights: &BotWeights) -> f32 {
+    let mut game = game.clone();
+    if game.topped_out || !game.spawn_piece() {
+        return TOPPED_OUT_SCORE;
+    }
+    candidates(&game, reward, weights)
+        .into_iter()
+        .take(BEAM_WIDTH)
+        .map(|(_, after, reward, score)| {
+            if depth > 1 {
+                search(&after, depth - 1, reward, weights)
+            } else {
+                score
+            }
+        })
+        .fold(TOPPED_OUT_SCORE, f32::max)
+}
+
+// 搜索当前骨牌的最佳落点，depth为考虑的骨牌数（包括当前骨牌）
+pub fn best_placement(game: &Game, depth: usize, weights: &BotWeights) -> Option<Placement> {
+    candidates(game, 0.0, weights)
+        .into_iter()
+        .take(BEAM_WIDTH)
+        .map(|(placement, after, reward, score)| {
+            let score = if depth > 1 {
+                search(&after, depth - 1, reward, weights)
+            } else {
+                score
+            };
+            (placement, score)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(placement, _)| placement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 只有一项权重不为0，评分即为该项的计数
+    const NO_WEIGHTS: BotWeights = BotWeights {
+        aggregate_height: 0.0,
+        holes: 0.0,
+        bumpiness: 0.0,
+        wells: 0.0,
+        t_slots: 0.0,
+        line_clears: [0.0; 4],
+        t_spin: 0.0,
+    };
+
+    fn game_with(cells: &[(i32, i32)]) -> Game {
+        let mut game = Game::new(BoardSize::default(), 0);
+        game.board = cells
+            .iter()
+            .map(|(x, y)| (GARBAGE_COLOR, Block { x: *x, y: *y }))
+            .collect();
+        game
+    }
+
+    // 底部两行留出T-spin双消的槽位，(3, 2)的方块悬在槽位上方
+    fn tsd_field() -> Game {
+        let mut cells = Vec::new();
+        for x in 0..10 {
+            if x != 4 {
+                cells.push((x, 0));
+            }
+            if !(3..=5).contains(&x) {
+                cells.push((x, 1));
+            }
+        }
+        cells.extend((0..=3).map(|x| (x, 2)));
+        game_with(&cells)
+    }
+
+    fn tsd_blocks() -> Vec<Block> {
+        sorted_blocks(&[
+            Block { x: 4, y: 0 },
+            Block { x: 3, y: 1 },
+            Block { x: 4, y: 1 },
+            Block { x: 5, y: 1 },
+        ])
+    }
+
+    #[test]
+    fn finds_t_spin_under_overhang() {
+        let mut game = tsd_field();
+        game.queue.push_front(PieceType::T);
+        assert!(game.spawn_piece());
+        let placement = placements(&game)
+            .into_iter()
+            .find(|placement| placement.blocks == tsd_blocks() && placement.t_spin)
+            .expect("T-spin slot should be reachable");
+        // 悬空方块挡住了直接下落，需要软降后再旋转进入槽位
+        let soft_drop = placement
+            .inputs
+            .iter()
+            .position(|input| *input == BotInput::SoftDrop)
+            .expect("slot needs a soft drop");
+        assert!(placement.inputs[soft_drop..].contains(&BotInput::Rotate));
+
+        let (after, lines_cleared) = simulate(&game, &placement);
+        let lines_cleared = lines_cleared.unwrap();
+        assert_eq!(lines_cleared.count, 2);
+        assert!(lines_cleared.t_spin);
+        assert_eq!(after.board.len(), 4);
+    }
+
+    #[test]
+    fn finds_tuck_under_overhang() {
+        // (1, 2)和(2, 2)悬空，O骨牌只能软降后左移进入左下角
+        let mut game = game_with(&[(1, 2), (1, 3), (2, 2)]);
+        game.queue.push_front(PieceType::O);
+        assert!(game.spawn_piece());
+        let target = sorted_blocks(&[
+            Block { x: 0, y: 0 },
+            Block { x: 1, y: 0 },
+            Block { x: 0, y: 1 },
+            Block { x: 1, y: 1 },
+        ]);
+        let placement = placements(&game)
+            .into_iter()
+            .find(|placement| placement.blocks == target)
+            .expect("tuck should be reachable");
+        let soft_drop = placement
+            .inputs
+            .iter()
+            .position(|input| *input == BotInput::SoftDrop)
+            .expect("tuck needs a soft drop");
+        assert!(placement.inputs[soft_drop..].contains(&BotInput::Left));
+    }
+
+    #[test]
+    fn evaluate_counts_holes() {
+        // 第0列中间空一格，第1列下方空两格
+        let game = game_with(&[(0, 0), (0, 2), (1, 2)]);
+        let weights = BotWeights {
+            holes: 1.0,
+            ..NO_WEIGHTS
+        };
+        assert_eq!(evaluate(&game, &weights), 3.0);
+    }
+
+    #[test]
+    fn evaluate_counts_wells() {
+        // 第4列和第9列比两侧低两格，面板外视为与面板一样高
+        let cells: Vec<(i32, i32)> = (0..10)
+            .filter(|x| *x != 4 && *x != 9)
+            .flat_map(|x| [(x, 0), (x, 1)])
+            .collect();
+        let game = game_with(&cells);
+        let weights = BotWeights {
+            wells: 1.0,
+            ..NO_WEIGHTS
+        };
+        assert_eq!(evaluate(&game, &weights), 4.0);
+    }
+
+    #[test]
+    fn evaluate_counts_t_slots() {
+        let weights = BotWeights {
+            t_slots: 1.0,
+            ..NO_WEIGHTS
+        };
+        assert_eq!(evaluate(&tsd_field(), &weights), 1.0);
+        // 没有悬空方块时只有两个对角被占用
+        let mut game = tsd_field();
+        game.board.retain(|(_, block)| block.y < 2);
+        assert_eq!(evaluate(&game, &weights), 0.0);
+    }
+}
@@ -1,4 +1,8 @@
+use std::marker::PhantomData;
+
+use bevy::asset::{AssetLoader, LoadContext, io::Reader};
 use bevy::prelude::*;
+use serde::Deserialize;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, States, Default)]
 pub enum AppState {
@@ -8,8 +12,11 @@ pub enum AppState {
     GameOver,
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, States, Default)]
 pub enum GameState {
+    // 开局倒计时，结束后进入GamePlaying
+    GameCountdown,
     GamePlaying,
     GamePaused,
     GameRestarted,
@@ -17,6 +24,56 @@ pub enum GameState {
     GameQuited,
 }
 
+// 游戏模式
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Resource, Default)]
+pub enum GameMode {
+    // 经典模式，无限进行直到方块堆到顶部
+    #[default]
+    Marathon,
+    // 生存模式，每隔一段时间从底部升起一行垃圾行
+    Survival,
+    // 大师模式，仿照TGM的20G高速模式
+    Master,
+    // 禅模式，默认没有重力，堆到顶部时清除上半部分面板而不结束游戏
+    Zen,
+    // 谜题模式，从数据文件加载初始面板、骨牌序列和目标
+    Puzzle,
+    // 隐形模式，锁定后的方块逐渐消失，消行时短暂重现
+    Invisible,
+    // 大方块模式，每个方块占据2x2格子，以两列为单位移动，每两行算作一行
+    Big,
+    // 每日挑战，根据日期生成固定的骨牌序列，记录当天成绩
+    Daily,
+    // 操作训练，提示每个骨牌的最少操作并统计失误率
+    Finesse,
+    // 定式练习，按固定骨牌顺序提示目标位置，放错时重试
+    Opener,
+    // 镜像模式，面板左右镜像显示
+    Mirror,
+    // 重力翻转模式，面板定期上下翻转
+    GravityFlip,
+    // 旋转模式，面板定期旋转180°
+    BoardTurn,
+    // 连锁模式，消行后不相连的方块块各自下落，可能引起连锁消行
+    Cascade,
+    // 4-wide连击练习，两侧墙壁消行后自动补齐
+    FourWide,
+    // 奶酪练习，消除40行带空缺的垃圾行
+    Cheese,
+    // 清理练习，随机生成高低不平的面板，清除后继续生成
+    Downstack,
+    // 双人对战，两个面板并排，各自使用一组按键
+    Versus,
+    // 人机对战，玩家2由电脑操作
+    VersusCpu,
+}
+
+impl GameMode {
+    pub fn is_versus(&self) -> bool {
+        matches!(self, GameMode::Versus | GameMode::VersusCpu)
+    }
+}
+
 #[derive(Debug, Resource)]
 pub struct GameAudios {
     pub drop: Handle<AudioSource>,
@@ -32,3 +89,46 @@ pub fn setup_game_audios(mut command: Commands, asset_server: Res<AssetServer>)
     };
     command.insert_resource(game_audios);
 }
+
+pub fn reset_game_mode(mut game_mode: ResMut<GameMode>) {
+    *game_mode = GameMode::default();
+}
+
+// 通用的RON格式资源加载器
+pub struct RonAssetLoader<A> {
+    extensions: &'static [&'static str],
+    _marker: PhantomData<A>,
+}
+
+impl<A> RonAssetLoader<A> {
+    pub fn new(extensions: &'static [&'static str]) -> Self {
+        RonAssetLoader {
+            extensions,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<A> AssetLoader for RonAssetLoader<A>
+where
+    A: Asset + for<'de> Deserialize<'de>,
+{
+    type Asset = A;
+    type Settings = ();
+    type Error = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<A, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        Ok(ron::de::from_bytes(&bytes)?)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        self.extensions
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::common::*;
+use crate::piece::*;
+use crate::timing::GameTimers;
+use crate::variant::BoardView;
+
+// 倒计时从3开始，每步的时长
+pub const COUNTDOWN_FROM: u32 = 3;
+pub const COUNTDOWN_STEP: Duration = Duration::from_millis(700);
+
+#[derive(Debug, Resource)]
+pub struct Countdown {
+    // 剩余步数，为0时显示GO
+    pub remaining: u32,
+    pub timer: Timer,
+}
+
+impl Default for Countdown {
+    fn default() -> Self {
+        Countdown {
+            remaining: COUNTDOWN_FROM,
+            timer: Timer::new(COUNTDOWN_STEP, TimerMode::Repeating),
+        }
+    }
+}
+
+// 倒计时文字，GO显示一步后移除
+#[derive(Debug, Component)]
+pub struct CountdownText;
+
+pub fn setup_countdown(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    mut countdown: ResMut<Countdown>,
+    mut initial_rotation: ResMut<InitialRotation>,
+    q_text: Query<Entity, With<CountdownText>>,
+) {
+    for entity in &q_text {
+        commands.entity(entity).despawn();
+    }
+    *countdown = Countdown::default();
+    initial_rotation.0 = false;
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        CountdownText,
+        children![(
+            Text::new(countdown.remaining.to_string()),
+            TextFont {
+                font_size: 120.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        )],
+    ));
+    spawn_drop_audio(&mut commands, &game_audios);
+}
+
+// 倒计时期间可以提前蓄力DAS，结束时按住旋转键则第一个骨牌出生时立即旋转
+// 游戏没有暂存功能，因此没有IHS
+pub fn tick_countdown(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    board_view: Res<BoardView>,
+    mut timers: ResMut<GameTimers>,
+    mut countdown: ResMut<Countdown>,
+    mut initial_rotation: ResMut<InitialRotation>,
+    mut game_state: ResMut<NextState<GameState>>,
+    q_countdown_text: Query<&Children, With<CountdownText>>,
+    mut q_text: Query<&mut Text>,
+    time: Res<Time>,
+) {
+    tick_das(
+        &keyboard_input,
+        horizontal_keys(&board_view),
+        &mut timers,
+        time.delta(),
+    );
+    countdown.timer.tick(time.delta());
+    if !countdown.timer.just_finished() {
+        return;
+    }
+    countdown.remaining -= 1;
+    let text = if countdown.remaining == 0 {
+        initial_rotation.0 = keyboard_input.pressed(KeyCode::ArrowUp);
+        game_state.set(GameState::GamePlaying);
+        commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+        "GO!".to_string()
+    } else {
+        spawn_drop_audio(&mut commands, &game_audios);
+        countdown.remaining.to_string()
+    };
+    info!("countdown: {}", text);
+    for children in &q_countdown_text {
+        for child in children {
+            if let Ok(mut child_text) = q_text.get_mut(*child) {
+                child_text.0 = text.clone();
+            }
+        }
+    }
+}
+
+// 游戏开始后GO显示一步的时间
+pub fn remove_countdown_text(
+    mut commands: Commands,
+    mut countdown: ResMut<Countdown>,
+    q_text: Query<Entity, With<CountdownText>>,
+    time: Res<Time>,
+) {
+    if q_text.is_empty() {
+        return;
+    }
+    countdown.timer.tick(time.delta());
+    if countdown.timer.just_finished() {
+        for entity in &q_text {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand::seq::IndexedRandom;
+
+use crate::board::LinesCleared;
+use crate::bot::*;
+use crate::engine::Game;
+use crate::tbp::TbpBot;
+
+// 电脑对手的难度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CpuDifficulty {
+    pub name: &'static str,
+    // 每秒最多放置的骨牌数
+    pub pps: f32,
+    // 搜索时考虑的骨牌数（包括当前骨牌）
+    pub depth: usize,
+    // 随机选择落点的概率
+    pub mistake_rate: f64,
+}
+
+// 可选的电脑难度
+pub const CPU_DIFFICULTY_PRESETS: [CpuDifficulty; 4] = [
+    CpuDifficulty {
+        name: "Easy",
+        pps: 0.8,
+        depth: 1,
+        mistake_rate: 0.15,
+    },
+    CpuDifficulty {
+        name: "Normal",
+        pps: 1.5,
+        depth: 2,
+        mistake_rate: 0.05,
+    },
+    CpuDifficulty {
+        name: "Hard",
+        pps: 2.5,
+        depth: 2,
+        mistake_rate: 0.0,
+    },
+    CpuDifficulty {
+        name: "Expert",
+        pps: 3.5,
+        depth: 3,
+        mistake_rate: 0.0,
+    },
+];
+
+impl CpuDifficulty {
+    // 下一个预设难度，循环切换
+    pub fn next_preset(&self) -> CpuDifficulty {
+        let index = CPU_DIFFICULTY_PRESETS
+            .iter()
+            .position(|preset| preset == self)
+            .map_or(0, |index| (index + 1) % CPU_DIFFICULTY_PRESETS.len());
+        CPU_DIFFICULTY_PRESETS[index]
+    }
+}
+
+// 主菜单中选择的电脑难度
+#[derive(Debug, Clone, Copy, Resource)]
+pub struct CpuDifficultySetting(pub CpuDifficulty);
+
+impl Default for CpuDifficultySetting {
+    fn default() -> Self {
+        CpuDifficultySetting(CPU_DIFFICULTY_PRESETS[1])
+    }
+}
+
+// 电脑和AI游玩使用的评估权重，启动参数--bot-weights <名称>选择assets/bots/<名称>.weights.ron
+#[derive(Debug, Resource)]
+pub struct BotWeightsHandle(pub Handle<BotWeights>);
+
+// 开局时生成电脑玩家需要用到，在Startup之前加载
+impl FromWorld for BotWeightsHandle {
+    fn from_world(world: &mut World) -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--bot-weights");
+        args.next();
+        let name = args.next().unwrap_or_else(|| "default".to_string());
+        let asset_server = world.resource::<AssetServer>();
+        BotWeightsHandle(asset_server.load(format!("bots/{}.weights.ron", name)))
+    }
+}
+
+// 权重文件未加载时使用默认权重
+pub fn current_bot_weights(
+    bot_weights_handle: &BotWeightsHandle,
+    bot_weights: &Assets<BotWeights>,
+) -> BotWeights {
+    bot_weights
+        .get(&bot_weights_handle.0)
+        .copied()
+        .unwrap_or_default()
+}
+
+pub fn cpu_difficulty_label(setting: &CpuDifficultySetting) -> String {
+    format!("CPU: {}", setting.0.name)
+}
+
+// 由电脑操作的玩家，每个骨牌出现时搜索落点，再把输入平均分配到骨牌的用时内
+#[derive(Debug, Component)]
+pub struct CpuPlayer {
+    pub difficulty: CpuDifficulty,
+    pub weights: BotWeights,
+    // 由外部机器人给出落点，仍按难度限制速度
+    pub external: Option<TbpBot>,
+    inputs: VecDeque<BotInput>,
+    timer: Timer,
+}
+
+impl CpuPlayer {
+    pub fn new(difficulty: CpuDifficulty) -> Self {
+        CpuPlayer {
+            difficulty,
+            weights: BotWeights::default(),
+            external: None,
+            inputs: VecDeque::new(),
+            timer: Timer::default(),
+        }
+    }
+
+    pub fn with_weights(mut self, weights: BotWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    pub fn with_external(mut self, external: Option<TbpBot>) -> Self {
+        self.external = external;
+        self
+    }
+
+    // 外部机器人还没有给出落点时返回false
+    fn plan(&mut self, game: &Game) -> bool {
+        let mut rng = rand::rng();
+        let placement = if let Some(external) = self.external.as_mut() {
+            let Some(placement) = external.poll(game) else {
+                return false;
+            };
+            Some(placement)
+        } else if rng.random_bool(self.difficulty.mistake_rate) {
+            placements(game).choose(&mut rng).cloned()
+        } else {
+            best_placement(game, self.difficulty.depth, &self.weights)
+        };
+        self.inputs = placement.map_or_else(
+            || VecDeque::from([BotInput::HardDrop]),
+            |placement| placement.inputs.into(),
+        );
+        let step = Duration::from_secs_f32(1.0 / self.difficulty.pps / self.inputs.len() as f32);
+        self.timer = Timer::new(step, TimerMode::Repeating);
+        true
+    }
+
+    // 返回骨牌是否移动，以及锁定时的消行
+    pub fn play(&mut self, game: &mut Game, delta: Duration) -> (bool, Option<LinesCleared>) {
+        if game.piece.is_none() {
+            return (false, None);
+        }
+        if self.inputs.is_empty() && !self.plan(game) {
+            return (false, None);
+        }
+        self.timer.tick(delta);
+        let mut moved = false;
+        for _ in 0..self.timer.times_finished_this_tick() {
+            let Some(input) = self.inputs.pop_front() else {
+                break;
+            };
+            if input == BotInput::HardDrop {
+                self.inputs.clear();
+                return (true, game.hard_drop());
+            }
+            moved |= apply_input(game, input);
+        }
+        (moved, None)
+    }
+}
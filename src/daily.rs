@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use crate::common::GameMode;
+use crate::menu::OnGameOverMenuScreen;
+use crate::piece::*;
+use crate::stats::{Lines, Score};
+
+// 每日挑战成绩保存位置（相对工作目录），web版没有文件系统，成绩只保留到关闭页面
+pub const DAILY_RECORDS_PATH: &str = "daily_records.ron";
+// 排行榜展示的成绩数
+pub const DAILY_LEADERBOARD_SIZE: usize = 5;
+
+// 当天（UTC）距1970-01-01的天数，所有人同一天得到相同的种子
+pub fn days_since_epoch() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() / 86400)
+        .unwrap_or_default()
+}
+
+// 将天数转换为 年-月-日
+pub fn format_date(days: u64) -> String {
+    // 以3月1日为一年的开始，闰日位于年末
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// 本局每日挑战的日期，跨过零点后仍记录在开局的日期
+#[derive(Debug, Default, Resource)]
+pub struct DailyChallenge {
+    pub date: String,
+    pub seed: u64,
+}
+
+// 一局每日挑战的成绩
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub score: u32,
+    pub lines: u32,
+}
+
+// 按日期保存的每日挑战成绩
+#[derive(Debug, Default, Resource, Serialize, Deserialize)]
+pub struct DailyRecords(pub BTreeMap<String, Vec<DailyResult>>);
+
+impl DailyRecords {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load() -> Self {
+        let Ok(content) = std::fs::read_to_string(DAILY_RECORDS_PATH) else {
+            return DailyRecords::default();
+        };
+        ron::from_str(&content).unwrap_or_else(|err| {
+            warn!("failed to parse {}: {}", DAILY_RECORDS_PATH, err);
+            DailyRecords::default()
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn load() -> Self {
+        info!("daily records are kept in memory only on the web");
+        DailyRecords::default()
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|err| err.to_string())
+            .and_then(|content| {
+                std::fs::write(DAILY_RECORDS_PATH, content).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            warn!("failed to save {}: {}", DAILY_RECORDS_PATH, err);
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn save(&self) {}
+
+    // 当天成绩按分数从高到低排列，返回(原序号, 成绩)
+    pub fn leaderboard(&self, date: &str) -> Vec<(usize, DailyResult)> {
+        let mut results: Vec<(usize, DailyResult)> = self
+            .0
+            .get(date)
+            .map(|results| results.iter().copied().enumerate().collect())
+            .unwrap_or_default();
+        results.sort_by(|(_, a), (_, b)| b.score.cmp(&a.score).then(b.lines.cmp(&a.lines)));
+        results
+    }
+}
+
+// 每日挑战说明及当天最高分
+#[derive(Debug, Component)]
+pub struct DailyInfoboard;
+
+pub fn setup_daily_records(mut commands: Commands) {
+    commands.insert_resource(DailyRecords::load());
+}
+
+pub fn setup_daily_infoboard(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        DailyInfoboard,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
+
+// 开局时根据日期设置种子并重新生成骨牌序列，其他模式恢复随机种子
+pub fn setup_daily_challenge(
+    game_mode: Res<GameMode>,
+    mut daily_challenge: ResMut<DailyChallenge>,
+    mut piece_rng: ResMut<PieceRng>,
+    mut piece_queue: ResMut<PieceQueue>,
+) {
+    if *game_mode != GameMode::Daily {
+        if daily_challenge.seed != 0 {
+            *daily_challenge = DailyChallenge::default();
+            *piece_rng = PieceRng::default();
+        }
+        return;
+    }
+    let days = days_since_epoch();
+    *daily_challenge = DailyChallenge {
+        date: format_date(days),
+        seed: days,
+    };
+    info!("daily challenge: {}", daily_challenge.date);
+    // 使用固定算法的随机数，不同平台和版本得到相同的序列
+    piece_rng.0 = ChaCha8Rng::seed_from_u64(daily_challenge.seed);
+    piece_queue.0 = VecDeque::from(random_7_pieces(&mut piece_rng.0));
+}
+
+// 游戏结束时保存成绩
+pub fn record_daily_result(
+    game_mode: Res<GameMode>,
+    daily_challenge: Res<DailyChallenge>,
+    score: Res<Score>,
+    lines: Res<Lines>,
+    mut daily_records: ResMut<DailyRecords>,
+) {
+    if *game_mode != GameMode::Daily {
+        return;
+    }
+    daily_records
+        .0
+        .entry(daily_challenge.date.clone())
+        .or_default()
+        .push(DailyResult {
+            score: score.0,
+            lines: lines.0,
+        });
+    daily_records.save();
+}
+
+// 游戏结束菜单旁展示当天排行榜，本局成绩高亮
+pub fn setup_daily_leaderboard(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    daily_challenge: Res<DailyChallenge>,
+    daily_records: Res<DailyRecords>,
+) {
+    if *game_mode != GameMode::Daily {
+        return;
+    }
+    let leaderboard = daily_records.leaderboard(&daily_challenge.date);
+    let latest = leaderboard.iter().map(|(index, _)| *index).max();
+    let rank = leaderboard
+        .iter()
+        .position(|(index, _)| Some(*index) == latest)
+        .map_or(0, |rank| rank + 1);
+    let mut text = format!("Daily {}\n", daily_challenge.date);
+    for (rank, (index, result)) in leaderboard.iter().take(DAILY_LEADERBOARD_SIZE).enumerate() {
+        let marker = if Some(*index) == latest { ">" } else { " " };
+        text.push_str(&format!(
+            "\n{}{}. {:>7}  {:>3} lines",
+            marker,
+            rank + 1,
+            result.score,
+            result.lines
+        ));
+    }
+    if rank > DAILY_LEADERBOARD_SIZE {
+        text.push_str(&format!("\n\nYour rank: {}/{}", rank, leaderboard.len()));
+    }
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            right: Val::Px(20.0),
+            ..default()
+        },
+        OnGameOverMenuScreen,
+        children![(
+            Text::new(text),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        )],
+    ));
+}
+
+pub fn update_daily_infoboard(
+    game_mode: Res<GameMode>,
+    daily_challenge: Res<DailyChallenge>,
+    daily_records: Res<DailyRecords>,
+    q_infoboard: Single<(&mut Visibility, &Children), With<DailyInfoboard>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let (mut visibility, children) = q_infoboard.into_inner();
+    if *game_mode != GameMode::Daily {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let best = daily_records
+        .leaderboard(&daily_challenge.date)
+        .first()
+        .map_or(0, |(_, result)| result.score);
+    let info = format!("Daily challenge {}  Best: {}", daily_challenge.date, best);
+    for child in children {
+        if let Ok(mut text) = q_text.get_mut(*child)
+            && text.0 != info
+        {
+            text.0 = info.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn daily_sequence_is_fixed_for_seed() {
+        let mut rng = ChaCha8Rng::seed_from_u64(20000);
+        let pieces: Vec<PieceType> = random_7_pieces(&mut rng)
+            .into_iter()
+            .map(|piece_config| piece_config.piece_type)
+            .collect();
+        assert_eq!(
+            pieces,
+            vec![
+                PieceType::J,
+                PieceType::I,
+                PieceType::Z,
+                PieceType::L,
+                PieceType::O,
+                PieceType::T,
+                PieceType::S,
+            ]
+        );
+    }
+
+    #[test]
+    fn format_date_from_days() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(20000), "2024-10-04");
+    }
+}
@@ -0,0 +1,464 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::attack::AttackTable;
+use crate::board::*;
+use crate::piece::*;
+use crate::piece_set::{ActivePieceSet, rotate_blocks, spawn_blocks};
+use crate::stats::line_score;
+
+// 队列中至少保留的骨牌数，用于预览
+const MIN_QUEUE_LEN: usize = PieceType::PIECE_AMOUNT as usize;
+
+// 当前下落的骨牌
+#[derive(Debug, Clone)]
+pub struct ActivePiece {
+    pub piece_type: PieceType,
+    pub blocks: Vec<Block>,
+    pub state: usize,
+}
+
+// 单个面板的统计
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GameStats {
+    pub pieces: u32,
+    pub lines: u32,
+    pub score: u32,
+    // 发送给对方的垃圾行数（抵消后）
+    pub attack: u32,
+    // 被自己的消行抵消的垃圾行数
+    pub cancelled: u32,
+}
+
+// 等待进入面板的一次攻击
+#[derive(Debug, Clone, Copy)]
+pub struct PendingGarbage {
+    pub rows: u32,
+    // 距离可以插入的剩余时间
+    pub remaining: Duration,
+    // 收到时的等待时间
+    pub delay: Duration,
+}
+
+impl PendingGarbage {
+    // 距离插入的紧迫程度，0为刚收到，1为可以插入
+    pub fn urgency(&self) -> f32 {
+        if self.delay.is_zero() {
+            1.0
+        } else {
+            1.0 - self.remaining.as_secs_f32() / self.delay.as_secs_f32()
+        }
+    }
+}
+
+// 不依赖ECS的单个面板对局逻辑，使用标准四格骨牌
+// 双人对战中每个玩家各持有一个，由外部按时间驱动
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub board_size: BoardSize,
+    // 已固定的方块及颜色
+    pub board: Vec<(Color, Block)>,
+    pub piece: Option<ActivePiece>,
+    pub queue: VecDeque<PieceType>,
+    pub rng: ChaCha8Rng,
+    pub stats: GameStats,
+    pub topped_out: bool,
+    // 连续消行的次数，未消行时清零
+    pub combo: u32,
+    // 上一次消行是否为困难消行
+    pub back_to_back: bool,
+    // 等待插入的垃圾行，按收到的顺序
+    pub pending_garbage: VecDeque<PendingGarbage>,
+    // 垃圾行空缺位置单独使用随机数，双方的骨牌序列保持一致
+    pub garbage_rng: ChaCha8Rng,
+    // 最后一次成功的操作是否为旋转，用于判断T-spin
+    last_move_is_rotation: bool,
+}
+
+impl Game {
+    pub fn new(board_size: BoardSize, seed: u64) -> Self {
+        let mut game = Game {
+            board_size,
+            board: Vec::new(),
+            piece: None,
+            queue: VecDeque::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            stats: GameStats::default(),
+            topped_out: false,
+            combo: 0,
+            back_to_back: false,
+            pending_garbage: VecDeque::new(),
+            garbage_rng: ChaCha8Rng::seed_from_u64(seed.wrapping_add(1)),
+            last_move_is_rotation: false,
+        };
+        game.fill_queue();
+        game
+    }
+
+    // bag7补充骨牌队列
+    fn fill_queue(&mut self) {
+        while self.queue.len() < MIN_QUEUE_LEN {
+            let bag = random_7_pieces(&mut self.rng);
+            self.queue
+                .extend(bag.iter().map(|piece_config| piece_config.piece_type));
+        }
+    }
+
+    pub fn board_blocks(&self) -> Vec<Block> {
+        self.board.iter().map(|(_, block)| *block).collect()
+    }
+
+    // 取出下一个骨牌放到出生位置，与面板重叠时结束
+    pub fn spawn_piece(&mut self) -> bool {
+        let Some(piece_type) = self.queue.pop_front() else {
+            return false;
+        };
+        self.fill_queue();
+        let Some(blocks) = spawn_blocks(piece_type, &ActivePieceSet::default(), &self.board_size)
+        else {
+            return false;
+        };
+        self.last_move_is_rotation = false;
+        if is_overlapping(&blocks, &self.board_blocks(), &self.board_size) {
+            self.topped_out = true;
+            return false;
+        }
+        self.piece = Some(ActivePiece {
+            piece_type,
+            blocks,
+            state: 0,
+        });
+        true
+    }
+
+    pub fn move_piece(&mut self, delta_x: i32) -> bool {
+        let board = self.board_blocks();
+        let Some(piece) = self.piece.as_mut() else {
+            return false;
+        };
+        let shifted = shift_piece(&piece.blocks, Some(delta_x), None);
+        if is_overlapping(&shifted, &board, &self.board_size) {
+            return false;
+        }
+        piece.blocks = shifted;
+        self.last_move_is_rotation = false;
+        true
+    }
+
+    pub fn rotate_piece(&mut self) -> bool {
+        let board = self.board_blocks();
+        let Some(piece) = self.piece.as_mut() else {
+            return false;
+        };
+        let Some((rotated, state)) = rotate_blocks(
+            piece.piece_type,
+            piece.state,
+            &piece.blocks,
+            &board,
+            &self.board_size,
+            &ActivePieceSet::default(),
+        ) else {
+            return false;
+        };
+        piece.blocks = rotated;
+        piece.state = state;
+        self.last_move_is_rotation = true;
+        true
+    }
+
+    // 当前骨牌可以直接下落的格数
+    pub fn drop_distance(&self) -> i32 {
+        self.piece.as_ref().map_or(0, |piece| {
+            drop_distance(&piece.blocks, &self.board_blocks(), &self.board_size)
+        })
+    }
+
+    // 下落最多rows格，返回实际下落的格数
+    pub fn drop_piece(&mut self, rows: i32) -> i32 {
+        let rows = rows.min(self.drop_distance());
+        if let Some(piece) = self.piece.as_mut()
+            && rows > 0
+        {
+            piece.blocks = shift_piece(&piece.blocks, None, Some(-rows));
+            self.last_move_is_rotation = false;
+        }
+        rows
+    }
+
+    // 落到底部并锁定
+    pub fn hard_drop(&mut self) -> Option<LinesCleared> {
+        self.drop_piece(self.drop_distance());
+        self.lock_piece()
+    }
+
+    // 将当前骨牌固定到面板并消行，方块堆到顶部时结束
+    pub fn lock_piece(&mut self) -> Option<LinesCleared> {
+        let piece = self.piece.take()?;
+        let board = self.board_blocks();
+        let t_spin = is_lock_t_spin(
+            piece.piece_type,
+            self.last_move_is_rotation,
+            &piece.blocks,
+            &board,
+            &self.board_size,
+        );
+        let color = piece_color(piece.piece_type);
+        self.board
+            .extend(piece.blocks.iter().map(|block| (color, *block)));
+        self.stats.pieces += 1;
+
+        // 与单人模式相同的消行规则，对战中没有连锁重力
+        let lines_cleared = clear_full_lines(&mut self.board, &self.board_size, false, t_spin)
+            .pop()
+            .unwrap_or(LinesCleared {
+                count: 0,
+                perfect_clear: false,
+                t_spin,
+                chain: 0,
+            });
+        self.stats.lines += lines_cleared.count;
+        self.stats.score += line_score(lines_cleared.count);
+        if is_topped_out(self.board.iter().map(|(_, block)| block), &self.board_size) {
+            self.topped_out = true;
+        }
+        Some(lines_cleared)
+    }
+
+    pub fn receive_garbage(&mut self, rows: u32, delay: Duration) {
+        if rows > 0 {
+            self.pending_garbage.push_back(PendingGarbage {
+                rows,
+                remaining: delay,
+                delay,
+            });
+        }
+    }
+
+    // 等待插入的垃圾行总数
+    pub fn pending_rows(&self) -> u32 {
+        self.pending_garbage
+            .iter()
+            .map(|garbage| garbage.rows)
+            .sum()
+    }
+
+    pub fn tick_garbage(&mut self, delta: Duration) {
+        for garbage in self.pending_garbage.iter_mut() {
+            garbage.remaining = garbage.remaining.saturating_sub(delta);
+        }
+    }
+
+    // 用攻击抵消等待中的垃圾行，返回抵消后剩余的攻击
+    pub fn cancel_garbage(&mut self, mut attack: u32) -> u32 {
+        while attack > 0 {
+            let Some(garbage) = self.pending_garbage.front_mut() else {
+                break;
+            };
+            let cancelled = attack.min(garbage.rows);
+            garbage.rows -= cancelled;
+            attack -= cancelled;
+            self.stats.cancelled += cancelled;
+            if garbage.rows == 0 {
+                self.pending_garbage.pop_front();
+            }
+        }
+        attack
+    }
+
+    // 插入等待时间已到的垃圾行，每次攻击的空缺位置相同（按规则可能改变）
+    pub fn insert_ready_garbage(&mut self, table: &AttackTable) {
+        let mut holes = Vec::new();
+        let mut budget = table.garbage_cap;
+        while budget > 0 {
+            let Some(garbage) = self.pending_garbage.front_mut() else {
+                break;
+            };
+            if !garbage.remaining.is_zero() {
+                break;
+            }
+            let rows = garbage.rows.min(budget);
+            let mut hole = self.garbage_rng.random_range(0..self.board_size.cols);
+            for _ in 0..rows {
+                holes.push(hole);
+                if self
+                    .garbage_rng
+                    .random_bool(table.hole_change_chance.clamp(0.0, 1.0))
+                {
+                    hole = self.garbage_rng.random_range(0..self.board_size.cols);
+                }
+            }
+            budget -= rows;
+            garbage.rows -= rows;
+            if garbage.rows == 0 {
+                self.pending_garbage.pop_front();
+            }
+        }
+        if holes.is_empty() {
+            return;
+        }
+        // 先收到的垃圾行在上面
+        holes.reverse();
+        let rows = holes.len() as i32;
+        for (_, block) in self.board.iter_mut() {
+            block.y += rows;
+        }
+        let garbage = garbage_row_blocks(&holes, self.board_size.cols);
+        self.board
+            .extend(garbage.into_iter().map(|block| (GARBAGE_COLOR, block)));
+        if is_topped_out(self.board.iter().map(|(_, block)| block), &self.board_size) {
+            self.topped_out = true;
+        }
+    }
+
+    // 骨牌锁定后结算攻击：更新连击和B2B，先抵消等待中的垃圾行，
+    // 未消行时插入已到时间的垃圾行，返回发送给对方的垃圾行数
+    pub fn resolve_attack(&mut self, lines_cleared: &LinesCleared, table: &AttackTable) -> u32 {
+        if lines_cleared.count == 0 {
+            self.combo = 0;
+            self.insert_ready_garbage(table);
+            return 0;
+        }
+        let mut attack = table.attack(lines_cleared, self.combo, self.back_to_back);
+        self.combo += 1;
+        self.back_to_back = AttackTable::is_difficult(lines_cleared);
+        if table.cancel {
+            attack = self.cancel_garbage(attack);
+        }
+        self.stats.attack += attack;
+        attack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_with(cells: &[(i32, i32)]) -> Game {
+        let mut game = Game::new(BoardSize::default(), 0);
+        game.board = cells
+            .iter()
+            .map(|(x, y)| (GARBAGE_COLOR, Block { x: *x, y: *y }))
+            .collect();
+        game
+    }
+
+    fn place(game: &mut Game, piece_type: PieceType, cells: &[(i32, i32)], rotated: bool) {
+        game.piece = Some(ActivePiece {
+            piece_type,
+            blocks: cells.iter().map(|(x, y)| Block { x: *x, y: *y }).collect(),
+            state: 0,
+        });
+        game.last_move_is_rotation = rotated;
+    }
+
+    fn lines(count: u32) -> LinesCleared {
+        LinesCleared {
+            count,
+            perfect_clear: false,
+            t_spin: false,
+            chain: 0,
+        }
+    }
+
+    // 底部两行留出T-spin双消的槽位，(3, 2)的方块悬在槽位上方
+    fn tsd_field() -> Game {
+        let mut cells = vec![(3, 2)];
+        for x in 0..10 {
+            if x != 4 {
+                cells.push((x, 0));
+            }
+            if !(3..=5).contains(&x) {
+                cells.push((x, 1));
+            }
+        }
+        game_with(&cells)
+    }
+
+    #[test]
+    fn lock_clears_lines_and_collapses() {
+        let cells: Vec<(i32, i32)> = (4..10).map(|x| (x, 0)).chain([(5, 1)]).collect();
+        let mut game = game_with(&cells);
+        let i_piece = [(0, 0), (1, 0), (2, 0), (3, 0)];
+        place(&mut game, PieceType::I, &i_piece, false);
+        let lines_cleared = game.lock_piece().unwrap();
+        assert_eq!(lines_cleared.count, 1);
+        assert!(!lines_cleared.t_spin);
+        assert!(!lines_cleared.perfect_clear);
+        assert_eq!(game.board_blocks(), vec![Block { x: 5, y: 0 }]);
+        assert_eq!(game.stats.lines, 1);
+        assert_eq!(game.stats.pieces, 1);
+    }
+
+    #[test]
+    fn lock_flags_t_spin_after_rotation() {
+        let slot = [(4, 0), (3, 1), (4, 1), (5, 1)];
+        let mut game = tsd_field();
+        place(&mut game, PieceType::T, &slot, true);
+        let lines_cleared = game.lock_piece().unwrap();
+        assert_eq!(lines_cleared.count, 2);
+        assert!(lines_cleared.t_spin);
+        assert_eq!(game.board_blocks(), vec![Block { x: 3, y: 0 }]);
+
+        // 最后一次操作不是旋转时不算T-spin
+        let mut game = tsd_field();
+        place(&mut game, PieceType::T, &slot, false);
+        assert!(!game.lock_piece().unwrap().t_spin);
+
+        // 未消行的T-spin也会标记
+        let mut game = tsd_field();
+        game.board.retain(|(_, block)| block.x != 0 || block.y != 0);
+        place(&mut game, PieceType::T, &slot, true);
+        let lines_cleared = game.lock_piece().unwrap();
+        assert_eq!(lines_cleared.count, 1);
+        assert!(lines_cleared.t_spin);
+    }
+
+    #[test]
+    fn attack_cancels_pending_garbage() {
+        let table = AttackTable::default();
+        let mut game = Game::new(BoardSize::default(), 0);
+        game.receive_garbage(1, Duration::ZERO);
+        game.receive_garbage(2, Duration::ZERO);
+        // 消四攻击4行，抵消3行后发送1行
+        assert_eq!(game.resolve_attack(&lines(4), &table), 1);
+        assert!(game.pending_garbage.is_empty());
+        assert_eq!(game.stats.cancelled, 3);
+        assert_eq!(game.stats.attack, 1);
+        assert!(game.back_to_back);
+        assert_eq!(game.combo, 1);
+        assert!(game.board.is_empty());
+    }
+
+    #[test]
+    fn garbage_is_inserted_after_delay() {
+        let table = AttackTable::default();
+        let mut game = game_with(&[(0, 0)]);
+        game.receive_garbage(2, Duration::from_secs(1));
+        game.combo = 3;
+        // 等待时间未到时不插入
+        assert_eq!(game.resolve_attack(&lines(0), &table), 0);
+        assert_eq!(game.combo, 0);
+        assert_eq!(game.board.len(), 1);
+
+        game.tick_garbage(Duration::from_secs(1));
+        game.resolve_attack(&lines(0), &table);
+        assert!(game.pending_garbage.is_empty());
+        let cols = game.board_size.cols;
+        assert_eq!(game.board.len() as i32, 1 + 2 * (cols - 1));
+        // 原有方块上移，两行垃圾行的空缺位置相同
+        assert!(game.board_blocks().contains(&Block { x: 0, y: 2 }));
+        let holes: Vec<Vec<i32>> = (0..2)
+            .map(|y| {
+                (0..cols)
+                    .filter(|x| !game.board_blocks().contains(&Block { x: *x, y }))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(holes[0].len(), 1);
+        assert_eq!(holes[0], holes[1]);
+    }
+}
@@ -0,0 +1,396 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bevy::prelude::*;
+
+use crate::board::*;
+use crate::common::GameMode;
+use crate::piece::*;
+use crate::piece_set::*;
+
+// 计算最少操作时考虑的操作，下落不计入操作数
+const FINESSE_INPUTS: [PieceInput; 5] = [
+    PieceInput::Left,
+    PieceInput::Right,
+    PieceInput::DasLeft,
+    PieceInput::DasRight,
+    PieceInput::Rotate,
+];
+
+impl PieceInput {
+    pub fn label(&self) -> &'static str {
+        match self {
+            PieceInput::Left => "L",
+            PieceInput::Right => "R",
+            PieceInput::DasLeft => "DAS-L",
+            PieceInput::DasRight => "DAS-R",
+            PieceInput::Rotate => "CW",
+            PieceInput::SoftDrop => "SD",
+            PieceInput::HardDrop => "HD",
+        }
+    }
+}
+
+// 一次放置的操作评估
+#[derive(Debug, Clone)]
+pub struct FinesseResult {
+    pub piece_type: PieceType,
+    // 玩家实际的操作数
+    pub inputs: usize,
+    // 到达同一位置的最少操作
+    pub optimal: Vec<PieceInput>,
+}
+
+impl FinesseResult {
+    pub fn is_fault(&self) -> bool {
+        self.inputs > self.optimal.len()
+    }
+}
+
+// 本次运行期间的统计，不随重新开局清零
+#[derive(Debug, Default, Resource)]
+pub struct FinesseStats {
+    pub placements: u32,
+    pub faults: u32,
+    pub last: Option<FinesseResult>,
+}
+
+impl FinesseStats {
+    pub fn fault_rate(&self) -> f32 {
+        if self.placements == 0 {
+            0.0
+        } else {
+            self.faults as f32 / self.placements as f32 * 100.0
+        }
+    }
+}
+
+// 操作提示及失误率
+#[derive(Debug, Component)]
+pub struct FinesseInfoboard;
+
+fn sorted_cells(piece: &[Block]) -> Vec<(i32, i32)> {
+    let mut cells: Vec<(i32, i32)> = piece.iter().map(|block| (block.x, block.y)).collect();
+    cells.sort();
+    cells
+}
+
+// 在出生高度执行一次操作，无法执行时返回None
+fn apply_input(
+    input: PieceInput,
+    piece_type: PieceType,
+    (piece, state): &(Vec<Block>, usize),
+    board: &[Block],
+    board_size: &BoardSize,
+    active_piece_set: &ActivePieceSet,
+) -> Option<(Vec<Block>, usize)> {
+    let step = |piece: &[Block], delta_x| {
+        let shifted = shift_piece(piece, Some(delta_x), None);
+        (!is_overlapping(&shifted, board, board_size)).then_some(shifted)
+    };
+    let delta_x = match input {
+        PieceInput::Left | PieceInput::DasLeft => -1,
+        PieceInput::Right | PieceInput::DasRight => 1,
+        PieceInput::Rotate => {
+            return rotate_blocks(
+                piece_type,
+                *state,
+                piece,
+                board,
+                board_size,
+                active_piece_set,
+            );
+        }
+        PieceInput::SoftDrop | PieceInput::HardDrop => return None,
+    };
+    let mut moved = step(piece, delta_x)?;
+    if matches!(input, PieceInput::DasLeft | PieceInput::DasRight) {
+        // 长按一直移动到碰撞为止
+        while let Some(next) = step(&moved, delta_x) {
+            moved = next;
+        }
+    }
+    Some((moved, *state))
+}
+
+// 从出生位置出发广度优先搜索，得到每个硬降落点的最少操作序列
+pub fn optimal_inputs(
+    piece_type: PieceType,
+    spawn: Vec<Block>,
+    board: &[Block],
+    board_size: &BoardSize,
+    active_piece_set: &ActivePieceSet,
+) -> HashMap<Vec<(i32, i32)>, Vec<PieceInput>> {
+    let mut placements = HashMap::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert((sorted_cells(&spawn), 0));
+    queue.push_back(((spawn, 0), Vec::new()));
+    while let Some((node, path)) = queue.pop_front() {
+        let distance = drop_distance(&node.0, board, board_size);
+        placements
+            .entry(sorted_cells(&shift_piece(&node.0, None, Some(-distance))))
+            .or_insert_with(|| path.clone());
+        for input in FINESSE_INPUTS {
+            let Some(next) = apply_input(
+                input,
+                piece_type,
+                &node,
+                board,
+                board_size,
+                active_piece_set,
+            ) else {
+                continue;
+            };
+            if visited.insert((sorted_cells(&next.0), next.1)) {
+                let mut next_path = path.clone();
+                next_path.push(input);
+                queue.push_back((next, next_path));
+            }
+        }
+    }
+    placements
+}
+
+// 骨牌锁定时比较玩家操作与最少操作，需在消行前运行
+pub fn check_finesse(
+    board_size: Res<BoardSize>,
+    active_piece_set: Res<ActivePieceSet>,
+    piece_inputs: Res<PieceInputs>,
+    mut finesse_stats: ResMut<FinesseStats>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    q_board: Query<&Block, Without<PieceType>>,
+) {
+    for event in piece_locked_events.read() {
+        // 锁定的方块已经加入面板，计算时需排除
+        let board: Vec<Block> = q_board
+            .iter()
+            .filter(|block| !event.blocks.contains(block))
+            .copied()
+            .collect();
+        let Some(spawn) = spawn_blocks(event.piece_type, &active_piece_set, &board_size) else {
+            continue;
+        };
+        let placements = optimal_inputs(
+            event.piece_type,
+            spawn,
+            &board,
+            &board_size,
+            &active_piece_set,
+        );
+        // 需要软降后移动或旋转才能到达的位置（tuck、spin）不做评估
+        let Some(optimal) = placements.get(&sorted_cells(&event.blocks)) else {
+            info!("finesse skipped: placement is not reachable by a hard drop");
+            continue;
+        };
+        let result = FinesseResult {
+            piece_type: event.piece_type,
+            inputs: piece_inputs
+                .0
+                .iter()
+                .filter(|input| !matches!(input, PieceInput::SoftDrop | PieceInput::HardDrop))
+                .count(),
+            optimal: optimal.clone(),
+        };
+        finesse_stats.placements += 1;
+        if result.is_fault() {
+            finesse_stats.faults += 1;
+        }
+        info!(
+            "finesse: {:?} {} inputs, optimal {}",
+            result.piece_type,
+            result.inputs,
+            result.optimal.len()
+        );
+        finesse_stats.last = Some(result);
+    }
+}
+
+pub fn setup_finesse_infoboard(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        FinesseInfoboard,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
+
+pub fn update_finesse_infoboard(
+    game_mode: Res<GameMode>,
+    finesse_stats: Res<FinesseStats>,
+    q_infoboard: Single<(&mut Visibility, &Children), With<FinesseInfoboard>>,
+    mut q_text: Query<(&mut Text, &mut TextColor)>,
+) {
+    let (mut visibility, children) = q_infoboard.into_inner();
+    if *game_mode != GameMode::Finesse {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let mut info = format!(
+        "Finesse faults: {}/{} ({:.1}%)",
+        finesse_stats.faults,
+        finesse_stats.placements,
+        finesse_stats.fault_rate()
+    );
+    let mut color = Color::srgb(0.9, 0.9, 0.9);
+    if let Some(result) = &finesse_stats.last {
+        let optimal = if result.optimal.is_empty() {
+            "-".to_string()
+        } else {
+            result
+                .optimal
+                .iter()
+                .map(|input| input.label())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        info.push_str(&format!(
+            "\n{}: {} inputs, optimal {}",
+            if result.is_fault() { "Fault" } else { "OK" },
+            result.inputs,
+            optimal
+        ));
+        if result.is_fault() {
+            color = Color::srgb(0.9, 0.3, 0.3);
+        }
+    }
+    for child in children {
+        if let Ok((mut text, mut text_color)) = q_text.get_mut(*child) {
+            if text.0 != info {
+                text.0 = info.clone();
+            }
+            text_color.0 = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::timing::{GameTimers, GameTimings, frames};
+
+    const KEYS: (KeyCode, KeyCode) = (KeyCode::ArrowLeft, KeyCode::ArrowRight);
+
+    // 空面板上骨牌平放在各列时的最少操作数，cells为骨牌在第0列时的方块
+    fn optimal_counts(piece_type: PieceType, cells: &[(i32, i32)]) -> Vec<usize> {
+        let board_size = BoardSize::default();
+        let active_piece_set = ActivePieceSet::default();
+        let spawn = spawn_blocks(piece_type, &active_piece_set, &board_size).unwrap();
+        let placements = optimal_inputs(piece_type, spawn, &[], &board_size, &active_piece_set);
+        let width = cells.iter().map(|(x, _)| x + 1).max().unwrap();
+        (0..=board_size.cols - width)
+            .map(|x| {
+                let mut placement: Vec<(i32, i32)> =
+                    cells.iter().map(|(dx, y)| (x + dx, *y)).collect();
+                placement.sort();
+                placements[&placement].len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn o_piece_finesse() {
+        let cells = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        assert_eq!(
+            optimal_counts(PieceType::O, &cells),
+            vec![1, 2, 2, 1, 0, 1, 2, 2, 1]
+        );
+    }
+
+    #[test]
+    fn flat_i_piece_finesse() {
+        let cells = [(0, 0), (1, 0), (2, 0), (3, 0)];
+        assert_eq!(
+            optimal_counts(PieceType::I, &cells),
+            vec![1, 2, 1, 0, 1, 2, 1]
+        );
+    }
+
+    #[test]
+    fn optimal_inputs_use_das_to_walls() {
+        let board_size = BoardSize::default();
+        let active_piece_set = ActivePieceSet::default();
+        let spawn = spawn_blocks(PieceType::O, &active_piece_set, &board_size).unwrap();
+        let placements = optimal_inputs(PieceType::O, spawn, &[], &board_size, &active_piece_set);
+        assert_eq!(
+            placements[&vec![(0, 0), (0, 1), (1, 0), (1, 1)]],
+            vec![PieceInput::DasLeft]
+        );
+        assert_eq!(
+            placements[&vec![(1, 0), (1, 1), (2, 0), (2, 1)]],
+            vec![PieceInput::DasLeft, PieceInput::Right]
+        );
+    }
+
+    // 运行一帧的左右移动，piece_inputs为None时表示还没有骨牌（出块延迟中）
+    fn run_frame(
+        keyboard_input: &mut ButtonInput<KeyCode>,
+        timers: &mut GameTimers,
+        piece_inputs: Option<&mut PieceInputs>,
+    ) {
+        let (_, das_charged) = tick_das(keyboard_input, KEYS, timers, frames(1));
+        if let Some(piece_inputs) = piece_inputs {
+            record_shift_input(keyboard_input, KEYS, timers, das_charged, piece_inputs);
+        }
+        keyboard_input.clear();
+    }
+
+    #[test]
+    fn held_key_is_recorded_as_das() {
+        let mut keyboard_input = ButtonInput::default();
+        let mut timers = GameTimers::new(&GameTimings::default());
+        let mut piece_inputs = PieceInputs::default();
+        keyboard_input.press(KeyCode::ArrowLeft);
+        for _ in 0..10 {
+            run_frame(&mut keyboard_input, &mut timers, Some(&mut piece_inputs));
+        }
+        assert_eq!(piece_inputs.0, vec![PieceInput::DasLeft]);
+    }
+
+    #[test]
+    fn das_charged_before_spawn_is_recorded() {
+        let mut keyboard_input = ButtonInput::default();
+        let mut timers = GameTimers::new(&GameTimings {
+            are: Duration::from_millis(500),
+            ..default()
+        });
+        // 出块延迟中按住右键直到DAS蓄满
+        keyboard_input.press(KeyCode::ArrowRight);
+        for _ in 0..20 {
+            run_frame(&mut keyboard_input, &mut timers, None);
+        }
+        assert!(timers.das.finished());
+
+        // 新骨牌出生后直接长按移动，每个骨牌记一次长按
+        for _ in 0..2 {
+            let mut piece_inputs = PieceInputs::default();
+            for _ in 0..5 {
+                run_frame(&mut keyboard_input, &mut timers, Some(&mut piece_inputs));
+            }
+            assert_eq!(piece_inputs.0, vec![PieceInput::DasRight]);
+        }
+
+        // 松开后新骨牌没有操作
+        keyboard_input.release(KeyCode::ArrowRight);
+        let mut piece_inputs = PieceInputs::default();
+        run_frame(&mut keyboard_input, &mut timers, Some(&mut piece_inputs));
+        assert!(piece_inputs.0.is_empty());
+    }
+}
@@ -0,0 +1,518 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::board::*;
+use crate::common::*;
+use crate::master::format_duration;
+use crate::piece::PieceType;
+use crate::timing::GameTimers;
+
+// 4-wide井宽
+pub const FOUR_WIDE_WELL: i32 = 4;
+// 奶酪模式需要消除的垃圾行总数及面板上保持的行数
+pub const CHEESE_TOTAL_LINES: u32 = 40;
+pub const CHEESE_VISIBLE_LINES: u32 = 10;
+// 整洁奶酪中空缺位置保持不变的概率
+const CLEAN_CHEESE_REPEAT_CHANCE: f64 = 0.7;
+// 清理练习中方块下方出现空洞的概率
+const DOWNSTACK_HOLE_CHANCE: f64 = 0.15;
+
+// 4-wide井底残留的3个方块，相对井左下角的坐标
+const FOUR_WIDE_RESIDUES: [[(i32, i32); 3]; 6] = [
+    [(0, 0), (1, 0), (2, 0)],
+    [(1, 0), (2, 0), (3, 0)],
+    [(0, 0), (1, 0), (3, 0)],
+    [(0, 0), (2, 0), (3, 0)],
+    [(0, 0), (1, 0), (0, 1)],
+    [(2, 0), (3, 0), (3, 1)],
+];
+
+// 奶酪垃圾行的空缺规律
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CheesePattern {
+    // 每行空缺位置都与上一行不同
+    #[default]
+    Random,
+    // 空缺位置大多与上一行相同，可以连续消多行
+    Clean,
+    // 空缺位置逐行右移
+    Staircase,
+}
+
+impl CheesePattern {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CheesePattern::Random => "Random",
+            CheesePattern::Clean => "Clean",
+            CheesePattern::Staircase => "Staircase",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            CheesePattern::Random => CheesePattern::Clean,
+            CheesePattern::Clean => CheesePattern::Staircase,
+            CheesePattern::Staircase => CheesePattern::Random,
+        }
+    }
+
+    // 根据上一行的空缺位置生成下一行的空缺位置
+    pub fn next_hole(&self, last_hole: Option<i32>, cols: i32, rng: &mut impl Rng) -> i32 {
+        let Some(last_hole) = last_hole else {
+            return rng.random_range(0..cols);
+        };
+        match self {
+            CheesePattern::Clean if rng.random_bool(CLEAN_CHEESE_REPEAT_CHANCE) => last_hole,
+            CheesePattern::Random | CheesePattern::Clean => {
+                // 在其余列中随机选择
+                let hole = rng.random_range(0..cols - 1);
+                if hole >= last_hole { hole + 1 } else { hole }
+            }
+            CheesePattern::Staircase => (last_hole + 1) % cols,
+        }
+    }
+}
+
+// 生成面板的练习进度，奶酪空缺规律在重新开局时保留
+#[derive(Debug, Default, Resource)]
+pub struct GeneratorState {
+    pub cheese_pattern: CheesePattern,
+    // 最近插入的垃圾行空缺位置
+    pub last_hole: Option<i32>,
+    // 已插入和已消除的奶酪垃圾行数
+    pub cheese_spawned: u32,
+    pub cheese_cleared: u32,
+    // 当前及最高连击数
+    pub combo: u32,
+    pub best_combo: u32,
+    // 清理练习中已清空的面板数
+    pub fields_cleared: u32,
+}
+
+// 练习模式信息
+#[derive(Debug, Component)]
+pub struct GeneratorInfoboard;
+
+// 检查生成的面板：方块在面板内且不重复，没有满行，不会一开局就结束游戏
+pub fn is_valid_field(blocks: &[Block], board_size: &BoardSize) -> bool {
+    let mut cells = HashSet::new();
+    let mut row_counts = vec![0; board_size.rows as usize];
+    for block in blocks {
+        if block.x < 0
+            || block.x >= board_size.cols
+            || block.y < 0
+            || block.y >= board_size.rows - 1
+            || !cells.insert((block.x, block.y))
+        {
+            return false;
+        }
+        row_counts[block.y as usize] += 1;
+    }
+    row_counts.iter().all(|count| *count < board_size.cols)
+}
+
+// 4-wide井左侧的x坐标，井位于面板中间
+fn four_wide_well_left(board_size: &BoardSize) -> i32 {
+    (board_size.cols - FOUR_WIDE_WELL) / 2
+}
+
+// 4-wide两侧墙壁的高度
+fn four_wide_height(board_size: &BoardSize) -> i32 {
+    board_size.rows * 3 / 5
+}
+
+fn is_four_wide_wall(x: i32, board_size: &BoardSize) -> bool {
+    let left = four_wide_well_left(board_size);
+    x < left || x >= left + FOUR_WIDE_WELL
+}
+
+// 4-wide连击井：中间留4列，两侧填满，井底残留3个方块
+pub fn four_wide_field(board_size: &BoardSize, rng: &mut impl Rng) -> Vec<Block> {
+    let left = four_wide_well_left(board_size);
+    let mut blocks = Vec::new();
+    for y in 0..four_wide_height(board_size) {
+        for x in 0..board_size.cols {
+            if is_four_wide_wall(x, board_size) {
+                blocks.push(Block { x, y });
+            }
+        }
+    }
+    let residue = FOUR_WIDE_RESIDUES[rng.random_range(0..FOUR_WIDE_RESIDUES.len())];
+    for (x, y) in residue {
+        blocks.push(Block { x: left + x, y });
+    }
+    blocks
+}
+
+// 清理练习：高低不平且带空洞的随机面板，高度不超过面板一半
+pub fn downstack_field(board_size: &BoardSize, rng: &mut impl Rng) -> Vec<Block> {
+    let max_height = board_size.rows / 2;
+    let mut blocks = Vec::new();
+    for x in 0..board_size.cols {
+        let height = rng.random_range(1..=max_height);
+        for y in 0..height {
+            // 每列顶部的方块保留，空洞都被盖住
+            if y == height - 1 || !rng.random_bool(DOWNSTACK_HOLE_CHANCE) {
+                blocks.push(Block { x, y });
+            }
+        }
+    }
+    // 满行中随机去掉一个方块
+    for y in 0..max_height {
+        let row: Vec<usize> = (0..blocks.len()).filter(|i| blocks[*i].y == y).collect();
+        if row.len() as i32 == board_size.cols {
+            blocks.swap_remove(row[rng.random_range(0..row.len())]);
+        }
+    }
+    blocks
+}
+
+// 面板上剩余的奶酪垃圾行数
+fn garbage_rows(
+    q_garbage: &Query<Entity, With<Garbage>>,
+    q_board: &Query<(&mut Block, &mut Transform), Without<PieceType>>,
+) -> u32 {
+    q_garbage
+        .iter()
+        .filter_map(|entity| q_board.get(entity).ok())
+        .map(|(block, _)| block.y)
+        .collect::<HashSet<_>>()
+        .len() as u32
+}
+
+// 从底部补充奶酪垃圾行，直到面板上有CHEESE_VISIBLE_LINES行或达到总数
+fn refill_cheese(
+    commands: &mut Commands,
+    state: &mut GeneratorState,
+    remaining: u32,
+    board_size: &BoardSize,
+    q_board: &mut Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    q_piece: &mut Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    let count = CHEESE_VISIBLE_LINES
+        .saturating_sub(remaining)
+        .min(CHEESE_TOTAL_LINES - state.cheese_spawned);
+    if count == 0 {
+        return;
+    }
+    let mut rng = rand::rng();
+    // 新插入的行在最下面，先生成的空缺位置在上面
+    let mut holes = Vec::new();
+    for _ in 0..count {
+        let hole = state
+            .cheese_pattern
+            .next_hole(state.last_hole, board_size.cols, &mut rng);
+        state.last_hole = Some(hole);
+        holes.insert(0, hole);
+    }
+    insert_garbage_rows(commands, &holes, board_size, q_board, q_piece);
+    state.cheese_spawned += count;
+}
+
+pub fn setup_generator_infoboard(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        GeneratorInfoboard,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
+
+// 开局时生成初始面板，奶酪垃圾行在游戏开始后补充
+pub fn setup_generated_board(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
+    mut state: ResMut<GeneratorState>,
+) {
+    *state = GeneratorState {
+        cheese_pattern: state.cheese_pattern,
+        ..default()
+    };
+    let mut rng = rand::rng();
+    let blocks = match *game_mode {
+        GameMode::FourWide => four_wide_field(&board_size, &mut rng),
+        GameMode::Downstack => downstack_field(&board_size, &mut rng),
+        _ => return,
+    };
+    if !is_valid_field(&blocks, &board_size) {
+        warn!(
+            "generated field is not valid for board size {:?}",
+            board_size
+        );
+        return;
+    }
+    for block in blocks {
+        let entity = spawn_board_block(&mut commands, block, GARBAGE_COLOR, &board_size);
+        // 清理练习的方块全部消除后生成新面板
+        if *game_mode == GameMode::Downstack {
+            commands.entity(entity).insert(Garbage);
+        }
+    }
+}
+
+// 统计连击，连续放置的骨牌都消行时连击数增加
+pub fn track_combo(
+    mut state: ResMut<GeneratorState>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+) {
+    let locked = piece_locked_events.read().count() > 0;
+    let cleared = lines_cleared_events.read().any(|event| event.chain == 0);
+    if !locked {
+        return;
+    }
+    if cleared {
+        state.combo += 1;
+        state.best_combo = state.best_combo.max(state.combo);
+    } else {
+        state.combo = 0;
+    }
+}
+
+// 消行后补齐4-wide两侧的墙壁
+pub fn refill_four_wide_walls(
+    mut commands: Commands,
+    board_size: Res<BoardSize>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    q_board: Query<&Block, Without<PieceType>>,
+    q_piece: Query<&Block, With<PieceType>>,
+) {
+    if lines_cleared_events.read().count() == 0 {
+        return;
+    }
+    let occupied: HashSet<(i32, i32)> = q_board
+        .iter()
+        .chain(q_piece.iter())
+        .map(|block| (block.x, block.y))
+        .collect();
+    for y in 0..four_wide_height(&board_size) {
+        for x in 0..board_size.cols {
+            if is_four_wide_wall(x, &board_size) && !occupied.contains(&(x, y)) {
+                spawn_board_block(&mut commands, Block { x, y }, GARBAGE_COLOR, &board_size);
+            }
+        }
+    }
+}
+
+// 补充奶酪垃圾行，全部消除后结束
+pub fn maintain_cheese(
+    mut commands: Commands,
+    board_size: Res<BoardSize>,
+    timers: Res<GameTimers>,
+    mut state: ResMut<GeneratorState>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    q_garbage: Query<Entity, With<Garbage>>,
+    mut q_board: Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    mut q_piece: Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    let remaining = garbage_rows(&q_garbage, &q_board);
+    state.cheese_cleared = state.cheese_spawned - remaining;
+    if state.cheese_cleared >= CHEESE_TOTAL_LINES {
+        info!(
+            "cheese finished, pattern: {}, time: {}",
+            state.cheese_pattern.name(),
+            format_duration(timers.elapsed)
+        );
+        app_state.set(AppState::GameOver);
+        game_state.set(GameState::GameQuited);
+        return;
+    }
+    refill_cheese(
+        &mut commands,
+        &mut state,
+        remaining,
+        &board_size,
+        &mut q_board,
+        &mut q_piece,
+    );
+}
+
+// 生成的方块全部消除后，在剩余方块下方插入新的清理练习面板
+pub fn regenerate_downstack(
+    mut commands: Commands,
+    board_size: Res<BoardSize>,
+    mut state: ResMut<GeneratorState>,
+    q_garbage: Query<(), With<Garbage>>,
+    mut q_board: Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    mut q_piece: Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    if !q_garbage.is_empty() {
+        return;
+    }
+    state.fields_cleared += 1;
+    info!("downstack field cleared: {}", state.fields_cleared);
+    let blocks = downstack_field(&board_size, &mut rand::rng());
+    if !is_valid_field(&blocks, &board_size) {
+        warn!(
+            "generated field is not valid for board size {:?}",
+            board_size
+        );
+        return;
+    }
+    let rows = blocks.iter().map(|block| block.y + 1).max().unwrap_or(0);
+    insert_garbage_blocks(
+        &mut commands,
+        rows,
+        &blocks,
+        &board_size,
+        &mut q_board,
+        &mut q_piece,
+    );
+}
+
+// R键重新生成面板，奶酪模式下H键切换空缺规律
+pub fn handle_generator_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    game_mode: Res<GameMode>,
+    mut state: ResMut<GeneratorState>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if *game_mode == GameMode::Cheese && keyboard_input.just_pressed(KeyCode::KeyH) {
+        state.cheese_pattern = state.cheese_pattern.next();
+        game_state.set(GameState::GameRestarted);
+    } else if keyboard_input.just_pressed(KeyCode::KeyR) {
+        game_state.set(GameState::GameRestarted);
+    }
+}
+
+pub fn update_generator_infoboard(
+    game_mode: Res<GameMode>,
+    state: Res<GeneratorState>,
+    timers: Res<GameTimers>,
+    q_infoboard: Single<(&mut Visibility, &Children), With<GeneratorInfoboard>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let (mut visibility, children) = q_infoboard.into_inner();
+    let info = match *game_mode {
+        GameMode::FourWide => format!(
+            "4-wide combo: {} (best {})\nR: new board",
+            state.combo, state.best_combo
+        ),
+        GameMode::Cheese => format!(
+            "Cheese ({}): {}/{} lines, {}\nH: hole pattern, R: retry",
+            state.cheese_pattern.name(),
+            state.cheese_cleared,
+            CHEESE_TOTAL_LINES,
+            format_duration(timers.elapsed)
+        ),
+        GameMode::Downstack => format!(
+            "Downstack fields cleared: {}\nR: new board",
+            state.fields_cleared
+        ),
+        _ => {
+            *visibility = Visibility::Hidden;
+            return;
+        }
+    };
+    *visibility = Visibility::Visible;
+    for child in children {
+        if let Ok(mut text) = q_text.get_mut(*child)
+            && text.0 != info
+        {
+            text.0 = info.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    // 所有面板预设及其大方块模式
+    fn board_sizes() -> Vec<BoardSize> {
+        BOARD_SIZE_PRESETS
+            .iter()
+            .flat_map(|board_size| [*board_size, board_size.with_cell_size(2)])
+            .collect()
+    }
+
+    fn has_full_line(blocks: &[Block], board_size: &BoardSize) -> bool {
+        let blocks: Vec<((), Block)> = blocks.iter().map(|block| ((), *block)).collect();
+        !find_full_lines(&blocks, board_size).is_empty()
+    }
+
+    #[test]
+    fn invalid_fields_are_rejected() {
+        let board_size = BoardSize::new(4, 20);
+        let row: Vec<Block> = (0..4).map(|x| Block { x, y: 0 }).collect();
+        let duplicate = [Block { x: 0, y: 0 }, Block { x: 0, y: 0 }];
+        assert!(is_valid_field(&[], &board_size));
+        assert!(is_valid_field(&row[..3], &board_size));
+        // 满行
+        assert!(!is_valid_field(&row, &board_size));
+        assert!(!is_valid_field(&duplicate, &board_size));
+        // 超出边界或位于出生行
+        assert!(!is_valid_field(&[Block { x: 4, y: 0 }], &board_size));
+        assert!(!is_valid_field(&[Block { x: 0, y: -1 }], &board_size));
+        assert!(!is_valid_field(&[Block { x: 0, y: 19 }], &board_size));
+    }
+
+    #[test]
+    fn generated_fields_are_valid() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        for board_size in board_sizes() {
+            for _ in 0..200 {
+                let four_wide = four_wide_field(&board_size, &mut rng);
+                assert!(is_valid_field(&four_wide, &board_size), "{:?}", board_size);
+                let downstack = downstack_field(&board_size, &mut rng);
+                assert!(is_valid_field(&downstack, &board_size), "{:?}", board_size);
+            }
+        }
+    }
+
+    #[test]
+    fn downstack_never_leaves_full_rows() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for board_size in board_sizes() {
+            for _ in 0..500 {
+                let blocks = downstack_field(&board_size, &mut rng);
+                assert!(!has_full_line(&blocks, &board_size), "{:?}", board_size);
+            }
+        }
+    }
+
+    #[test]
+    fn cheese_holes_follow_pattern() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let cols = STANDARD_COL_COUNT;
+        for pattern in [
+            CheesePattern::Random,
+            CheesePattern::Clean,
+            CheesePattern::Staircase,
+        ] {
+            let mut last_hole = None;
+            for _ in 0..200 {
+                let hole = pattern.next_hole(last_hole, cols, &mut rng);
+                assert!((0..cols).contains(&hole));
+                match (pattern, last_hole) {
+                    (CheesePattern::Random, Some(last_hole)) => assert_ne!(hole, last_hole),
+                    (CheesePattern::Staircase, Some(last_hole)) => {
+                        assert_eq!(hole, (last_hole + 1) % cols)
+                    }
+                    _ => {}
+                }
+                last_hole = Some(hole);
+            }
+        }
+    }
+}
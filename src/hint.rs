@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy::tasks::futures::check_ready;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+
+use crate::autoplay::snapshot_game;
+use crate::board::*;
+use crate::bot::*;
+use crate::cpu::{BotWeightsHandle, current_bot_weights};
+use crate::engine::ActivePiece;
+use crate::piece::*;
+use crate::variant::BoardRoot;
+
+// 落点提示考虑的骨牌数（包括当前骨牌）
+const HINT_DEPTH: usize = 2;
+// 提示轮廓的颜色
+const HINT_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.8);
+// 轮廓线宽相对方块边长的比例
+const HINT_LINE_WIDTH: f32 = 0.12;
+
+// 搜索落点时的面板和骨牌，变化时重新搜索
+#[derive(Debug, Clone, PartialEq)]
+struct HintKey {
+    board: Vec<Block>,
+    piece_type: PieceType,
+    queue: Vec<PieceType>,
+}
+
+// 当前骨牌的落点提示，在后台线程中搜索，不影响帧率
+#[derive(Debug, Default, Resource)]
+pub struct PlacementHint {
+    pub enabled: bool,
+    key: Option<HintKey>,
+    task: Option<Task<Option<Vec<Block>>>>,
+    // 搜索得到的落点方块
+    pub blocks: Option<Vec<Block>>,
+}
+
+impl PlacementHint {
+    fn clear(&mut self) {
+        self.key = None;
+        self.task = None;
+        self.blocks = None;
+    }
+}
+
+// 落点提示的轮廓线，不带Block组件，不参与碰撞
+#[derive(Debug, Component)]
+pub struct PlacementHintEdge;
+
+// 按T键开关落点提示
+pub fn toggle_placement_hint(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut placement_hint: ResMut<PlacementHint>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyT) {
+        placement_hint.enabled = !placement_hint.enabled;
+        placement_hint.clear();
+        info!("placement hint: {}", placement_hint.enabled);
+    }
+}
+
+// 面板或骨牌变化时在后台开始搜索，搜索完成后更新提示
+// 只支持标准四格骨牌
+pub fn update_placement_hint(
+    mut placement_hint: ResMut<PlacementHint>,
+    q_piece: Query<(&PieceType, &Block, &RotationState)>,
+    q_board: Query<&Block, Without<PieceType>>,
+    board_size: Res<BoardSize>,
+    piece_queue: Res<PieceQueue>,
+    bot_weights_handle: Res<BotWeightsHandle>,
+    bot_weights: Res<Assets<BotWeights>>,
+) {
+    if !placement_hint.enabled {
+        return;
+    }
+    let Some((piece_type, _, rotation_state)) = q_piece.iter().next() else {
+        if placement_hint.key.is_some() {
+            placement_hint.clear();
+        }
+        return;
+    };
+    if matches!(piece_type, PieceType::Custom(_)) {
+        return;
+    }
+    let mut board: Vec<Block> = q_board.iter().copied().collect();
+    board.sort_by_key(|block| (block.y, block.x));
+    let key = HintKey {
+        board,
+        piece_type: *piece_type,
+        queue: piece_queue
+            .0
+            .iter()
+            .take(HINT_DEPTH - 1)
+            .map(|piece_config| piece_config.piece_type)
+            .collect(),
+    };
+    if placement_hint.key.as_ref() != Some(&key) {
+        let game = snapshot_game(
+            *board_size,
+            key.board.iter().copied(),
+            ActivePiece {
+                piece_type: *piece_type,
+                blocks: q_piece.iter().map(|(_, block, _)| *block).collect(),
+                state: rotation_state.0,
+            },
+            &piece_queue,
+        );
+        let weights = current_bot_weights(&bot_weights_handle, &bot_weights);
+        placement_hint.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            best_placement(&game, HINT_DEPTH, &weights).map(|placement| placement.blocks)
+        }));
+        placement_hint.key = Some(key);
+        placement_hint.blocks = None;
+    }
+    // 搜索未完成时不触发变化检测
+    let blocks = placement_hint
+        .bypass_change_detection()
+        .task
+        .as_mut()
+        .and_then(check_ready);
+    if let Some(blocks) = blocks {
+        placement_hint.task = None;
+        placement_hint.blocks = blocks;
+    }
+}
+
+// 提示变化时重新生成轮廓，只画落点形状的外边
+pub fn render_placement_hint(
+    mut commands: Commands,
+    placement_hint: Res<PlacementHint>,
+    board_size: Res<BoardSize>,
+    q_root: Single<Entity, With<BoardRoot>>,
+    q_edge: Query<Entity, With<PlacementHintEdge>>,
+) {
+    if !placement_hint.is_changed() {
+        return;
+    }
+    for entity in &q_edge {
+        commands.entity(entity).despawn();
+    }
+    let Some(blocks) = placement_hint.blocks.as_ref() else {
+        return;
+    };
+    let block_length = board_size.block_length();
+    let line_width = block_length * HINT_LINE_WIDTH;
+    for block in blocks {
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let neighbor = Block {
+                x: block.x + dx,
+                y: block.y + dy,
+            };
+            if blocks.contains(&neighbor) {
+                continue;
+            }
+            let offset = (block_length - line_width) / 2.0;
+            let mut translation = block.translation(&board_size)
+                + Vec3::new(dx as f32 * offset, dy as f32 * offset, 0.0);
+            // 位于骨牌方块下方
+            translation.z = -1.0;
+            let size = if dx != 0 {
+                Vec2::new(line_width, block_length)
+            } else {
+                Vec2::new(block_length, line_width)
+            };
+            commands.spawn((
+                Sprite {
+                    color: HINT_COLOR,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                Transform::from_translation(translation),
+                PlacementHintEdge,
+                ChildOf(*q_root),
+            ));
+        }
+    }
+}
+
+pub fn clear_placement_hint(mut placement_hint: ResMut<PlacementHint>) {
+    placement_hint.clear();
+}
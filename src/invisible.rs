@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::board::*;
+use crate::piece::PieceType;
+use crate::timing::GameTimers;
+
+// 方块锁定后开始消失的延迟
+pub const INVISIBLE_FADE_DELAY: Duration = Duration::from_secs(3);
+// 方块从开始消失到完全隐形的时长
+pub const INVISIBLE_FADE_DURATION: Duration = Duration::from_millis(500);
+// 消行后整个面板重现的时长
+pub const INVISIBLE_REVEAL_DURATION: Duration = Duration::from_millis(800);
+
+// 隐形模式设置及状态
+#[derive(Debug, Resource)]
+pub struct InvisibleStack {
+    // 为零时方块锁定后立即消失
+    pub fade_delay: Duration,
+    // 消行后的重现计时器
+    pub reveal: Timer,
+}
+
+impl Default for InvisibleStack {
+    fn default() -> Self {
+        let mut reveal = Timer::new(INVISIBLE_REVEAL_DURATION, TimerMode::Once);
+        // 开局时不重现
+        reveal.tick(INVISIBLE_REVEAL_DURATION);
+        InvisibleStack {
+            fade_delay: INVISIBLE_FADE_DELAY,
+            reveal,
+        }
+    }
+}
+
+// 锁定时长为age的方块的不透明度
+pub fn stack_alpha(age: Duration, fade_delay: Duration) -> f32 {
+    if fade_delay.is_zero() {
+        return 0.0;
+    }
+    let fading = age.saturating_sub(fade_delay);
+    1.0 - (fading.as_secs_f32() / INVISIBLE_FADE_DURATION.as_secs_f32()).min(1.0)
+}
+
+// 按F键切换延迟消失和立即消失
+pub fn toggle_fade_delay(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut invisible_stack: ResMut<InvisibleStack>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyF) {
+        invisible_stack.fade_delay = if invisible_stack.fade_delay.is_zero() {
+            INVISIBLE_FADE_DELAY
+        } else {
+            Duration::ZERO
+        };
+        info!("invisible fade delay: {:?}", invisible_stack.fade_delay);
+    }
+}
+
+// 根据锁定时刻淡出面板方块，消行时短暂重现
+pub fn fade_stack(
+    time: Res<Time>,
+    timers: Res<GameTimers>,
+    mut invisible_stack: ResMut<InvisibleStack>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    mut q_board: Query<(&mut Sprite, Option<&LockedAt>), (With<Block>, Without<PieceType>)>,
+) {
+    if lines_cleared_events.read().count() > 0 {
+        invisible_stack.reveal.reset();
+    }
+    invisible_stack.reveal.tick(time.delta());
+    let revealing = !invisible_stack.reveal.finished();
+    for (mut sprite, locked_at) in &mut q_board {
+        // 没有锁定时刻的方块（如初始面板）视为开局时锁定
+        let age = timers.elapsed - locked_at.map_or(Duration::ZERO, |locked_at| locked_at.0);
+        let alpha = if revealing {
+            1.0
+        } else {
+            stack_alpha(age, invisible_stack.fade_delay)
+        };
+        if sprite.color.alpha() != alpha {
+            sprite.color.set_alpha(alpha);
+        }
+    }
+}
+
+// 游戏结束时展示整个面板
+pub fn reveal_stack(mut q_board: Query<&mut Sprite, (With<Block>, Without<PieceType>)>) {
+    for mut sprite in &mut q_board {
+        sprite.color.set_alpha(1.0);
+    }
+}
+
+pub fn reset_invisible_stack(mut invisible_stack: ResMut<InvisibleStack>) {
+    *invisible_stack = InvisibleStack::default();
+}
@@ -0,0 +1,26 @@
+pub mod attack;
+pub mod autoplay;
+pub mod board;
+pub mod bot;
+pub mod common;
+pub mod countdown;
+pub mod cpu;
+pub mod daily;
+pub mod engine;
+pub mod finesse;
+pub mod generator;
+pub mod hint;
+pub mod invisible;
+pub mod master;
+pub mod menu;
+pub mod opener;
+pub mod piece;
+pub mod piece_set;
+pub mod puzzle;
+pub mod simulation;
+pub mod stats;
+pub mod survival;
+pub mod tbp;
+pub mod timing;
+pub mod variant;
+pub mod versus;
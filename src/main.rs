@@ -1,17 +1,28 @@
-use std::time::Duration;
-
 use bevy::{prelude::*, transform::TransformSystem};
-use board::*;
-use common::*;
-use menu::*;
-use piece::*;
-use stats::*;
-
-mod board;
-mod common;
-mod menu;
-mod piece;
-mod stats;
+use tetris::attack::*;
+use tetris::autoplay::*;
+use tetris::board::*;
+use tetris::bot::BotWeights;
+use tetris::common::*;
+use tetris::countdown::*;
+use tetris::cpu::*;
+use tetris::daily::*;
+use tetris::finesse::*;
+use tetris::generator::*;
+use tetris::hint::*;
+use tetris::invisible::*;
+use tetris::master::*;
+use tetris::menu::*;
+use tetris::opener::*;
+use tetris::piece::*;
+use tetris::piece_set::*;
+use tetris::puzzle::*;
+use tetris::stats::*;
+use tetris::survival::*;
+use tetris::tbp::TbpBotCommand;
+use tetris::timing::*;
+use tetris::variant::*;
+use tetris::versus::*;
 
 fn main() {
     App::new()
@@ -19,29 +30,84 @@ fn main() {
         .insert_resource(Lines(0))
         .insert_resource(ClearColor(Color::BLACK))
         .insert_resource(NextPieceType(None))
-        .insert_resource(AutoMovePieceDownTimer(Timer::new(
-            NORMAL_MOVE_DURATION,
-            TimerMode::Repeating,
-        )))
-        .insert_resource(ManuallyMoveTimer(Timer::new(
-            Duration::from_millis(100),
-            TimerMode::Once,
-        )))
-        .insert_resource(RemovePieceComponentTimer(Timer::new(
-            Duration::from_millis(300),
-            TimerMode::Once,
-        )))
+        .init_resource::<GameMode>()
+        .init_resource::<BoardSize>()
+        .init_resource::<BoardSizeSetting>()
+        .init_resource::<GameTimings>()
+        .init_resource::<GameTimers>()
+        .init_resource::<GarbageRiseTimer>()
+        .init_resource::<MasterState>()
+        .init_resource::<LastMoveIsRotation>()
+        .init_resource::<PieceInputs>()
+        .init_resource::<CurrentPuzzle>()
+        .init_resource::<PuzzleProgress>()
+        .init_resource::<InvisibleStack>()
+        .init_resource::<PieceRng>()
+        .init_resource::<DailyChallenge>()
+        .init_resource::<PieceSetSetting>()
+        .init_resource::<ActivePieceSet>()
+        .init_resource::<FinesseStats>()
+        .init_resource::<CurrentOpener>()
+        .init_resource::<OpenerProgress>()
+        .init_resource::<BoardView>()
+        .init_resource::<VariantTimer>()
+        .init_resource::<GeneratorState>()
+        .init_resource::<Countdown>()
+        .init_resource::<InitialRotation>()
+        .init_resource::<VersusResult>()
+        .init_resource::<CpuDifficultySetting>()
+        .init_resource::<AttractTimer>()
+        .init_resource::<AiPlay>()
+        .init_resource::<AiPlayBot>()
+        .init_resource::<PlacementHint>()
+        .insert_resource(TbpBotCommand::from_args())
+        .add_event::<PieceLocked>()
+        .add_event::<LinesCleared>()
         .add_plugins(DefaultPlugins)
+        .init_asset::<PuzzleSet>()
+        .register_asset_loader(RonAssetLoader::<PuzzleSet>::new(&["puzzles.ron"]))
+        .init_asset::<PieceSet>()
+        .register_asset_loader(RonAssetLoader::<PieceSet>::new(&["pieces.ron"]))
+        .init_asset::<OpenerSet>()
+        .register_asset_loader(RonAssetLoader::<OpenerSet>::new(&["openers.ron"]))
+        .init_asset::<AttackTable>()
+        .register_asset_loader(RonAssetLoader::<AttackTable>::new(&["attack.ron"]))
+        .init_asset::<BotWeights>()
+        .register_asset_loader(RonAssetLoader::<BotWeights>::new(&["weights.ron"]))
+        .init_resource::<PieceSetHandles>()
+        .init_resource::<BotWeightsHandle>()
         .init_state::<AppState>()
         .init_state::<GameState>()
         .add_systems(
             Startup,
             (
                 setup_camera,
+                setup_board_root,
                 setup_game_board,
                 setup_game_audios,
                 setup_stats_boards,
                 setup_piece_queue,
+                setup_garbage_rise_meter,
+                setup_master_board,
+                setup_puzzle_set,
+                setup_puzzle_infoboard,
+                setup_daily_records,
+                setup_daily_infoboard,
+                setup_finesse_infoboard,
+                setup_opener_set,
+                setup_opener_infoboard,
+                setup_generator_infoboard,
+                setup_attack_table,
+            ),
+        )
+        // 面板大小改变时调整布局
+        .add_systems(
+            Update,
+            (
+                apply_board_size.run_if(resource_changed::<BoardSizeSetting>),
+                (update_game_board, layout_stats_boards)
+                    .after(apply_board_size)
+                    .run_if(resource_changed::<BoardSize>),
             ),
         )
         // Main Menu
@@ -53,14 +119,56 @@ fn main() {
                 reset_score,
                 reset_lines,
                 clear_next_piece_board,
+                reset_game_mode,
+                apply_board_size.after(reset_game_mode),
+                reset_game_timings,
+                reset_garbage_rise_timer,
+                reset_master_state,
+                reset_current_puzzle,
+                reset_current_opener,
+                reset_invisible_stack,
+                reset_board_view.after(reset_game_mode),
+                despawn_screen::<OpenerTarget>,
+                despawn_screen::<CountdownText>,
+                setup_versus_boards.after(reset_game_mode),
+                reset_attract_timer,
+                reset_ai_play,
+                clear_placement_hint,
             ),
         )
         .add_systems(
             OnExit(AppState::MainMenu),
-            despawn_screen::<OnMainMenuScreen>,
+            (
+                despawn_screen::<OnMainMenuScreen>,
+                despawn_screen::<DemoGame>,
+            ),
+        )
+        .add_systems(
+            OnEnter(AppState::InGame),
+            (
+                reset_game_timings,
+                apply_board_size,
+                apply_piece_set,
+                setup_puzzle,
+                setup_opener,
+                setup_daily_challenge,
+                setup_generated_board,
+                reset_board_view,
+                setup_versus_boards,
+            )
+                .chain(),
         )
         // Game Over Menu
-        .add_systems(OnEnter(AppState::GameOver), setup_game_over_menu)
+        .add_systems(
+            OnEnter(AppState::GameOver),
+            (
+                setup_game_over_menu,
+                reveal_stack,
+                (record_daily_result, setup_daily_leaderboard).chain(),
+                setup_versus_results,
+                clear_placement_hint,
+            ),
+        )
         .add_systems(
             OnExit(AppState::GameOver),
             (
@@ -69,6 +177,10 @@ fn main() {
                 reset_score,
                 reset_lines,
                 clear_next_piece_board,
+                reset_game_timings,
+                reset_garbage_rise_timer,
+                reset_master_state,
+                reset_invisible_stack,
             ),
         )
         // Game Playing
@@ -76,13 +188,14 @@ fn main() {
             PostUpdate,
             (
                 check_collision,
-                remove_piece_component,
-                check_game_over.after(remove_piece_component),
+                remove_piece_component.after(check_collision),
+                check_game_over.after(check_full_line),
                 check_full_line
                     .after(remove_piece_component)
                     .before(TransformSystem::TransformPropagate),
             )
-                .run_if(in_state(GameState::GamePlaying)),
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(not(in_versus)),
         )
         .add_systems(
             Update,
@@ -94,8 +207,173 @@ fn main() {
                 update_linesboard,
                 update_next_piece_board,
                 control_piece_visibility,
+                score_cleared_lines.run_if(not(resource_equals(GameMode::Master))),
+                apply_game_timings,
+                tick_game_elapsed,
+            )
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(not(in_versus)),
+        )
+        // Survival
+        .add_systems(
+            Update,
+            rise_garbage
+                .after(move_piece)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Survival)),
+        )
+        .add_systems(Update, update_garbage_rise_meter)
+        // Master
+        .add_systems(
+            Update,
+            (
+                tick_master_time,
+                update_master_level,
+                update_master_timings.after(update_master_level),
+                check_master_finished.after(update_master_level),
+            )
+                .before(apply_game_timings)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Master)),
+        )
+        .add_systems(Update, update_master_board)
+        // Puzzle
+        .add_systems(
+            Update,
+            (check_puzzle_goal, handle_puzzle_input)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Puzzle)),
+        )
+        .add_systems(Update, update_puzzle_infoboard)
+        // Daily
+        .add_systems(Update, update_daily_infoboard)
+        // Finesse
+        .add_systems(
+            PostUpdate,
+            check_finesse
+                .after(remove_piece_component)
+                .before(check_full_line)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Finesse)),
+        )
+        .add_systems(Update, update_finesse_infoboard)
+        // Opener
+        .add_systems(
+            PostUpdate,
+            check_opener_placement
+                .after(remove_piece_component)
+                .before(check_full_line)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Opener)),
+        )
+        .add_systems(
+            Update,
+            handle_opener_input
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Opener)),
+        )
+        .add_systems(Update, (update_opener_targets, update_opener_infoboard))
+        // Variants
+        .add_systems(
+            Update,
+            toggle_board_variant
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(
+                    resource_equals(GameMode::GravityFlip).or(resource_equals(GameMode::BoardTurn)),
+                ),
+        )
+        .add_systems(Update, animate_board_root)
+        .add_systems(
+            PostUpdate,
+            attach_to_board_root.before(TransformSystem::TransformPropagate),
+        )
+        // Generators
+        .add_systems(
+            Update,
+            (
+                track_combo,
+                refill_four_wide_walls.run_if(resource_equals(GameMode::FourWide)),
+                maintain_cheese.run_if(resource_equals(GameMode::Cheese)),
+                regenerate_downstack.run_if(resource_equals(GameMode::Downstack)),
+                handle_generator_input,
+            )
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(
+                    resource_equals(GameMode::FourWide)
+                        .or(resource_equals(GameMode::Cheese))
+                        .or(resource_equals(GameMode::Downstack)),
+                ),
+        )
+        .add_systems(Update, update_generator_infoboard)
+        // Versus
+        .add_systems(
+            Update,
+            (play_versus, check_versus_finished.after(play_versus))
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(in_versus),
+        )
+        .add_systems(
+            Update,
+            (
+                render_versus_boards.after(play_versus).after(play_demo),
+                update_versus_stats,
+                update_incoming_garbage_meters.after(play_versus),
+            ),
+        )
+        // Autoplay
+        .add_systems(
+            Update,
+            (update_attract_mode, play_demo.after(update_attract_mode))
+                .run_if(in_state(AppState::MainMenu)),
+        )
+        .add_systems(
+            Update,
+            (
+                toggle_ai_play,
+                play_ai.before(move_piece).before(rotate_piece),
             )
-                .run_if(in_state(GameState::GamePlaying)),
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(not(in_versus)),
+        )
+        // Hint
+        .add_systems(
+            Update,
+            (
+                toggle_placement_hint,
+                update_placement_hint
+                    .after(toggle_placement_hint)
+                    .after(auto_generate_new_piece)
+                    .after(move_piece)
+                    .after(rotate_piece),
+            )
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(not(in_versus)),
+        )
+        .add_systems(Update, render_placement_hint.after(update_placement_hint))
+        // Zen
+        .add_systems(
+            Update,
+            toggle_gravity
+                .before(apply_game_timings)
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Zen)),
+        )
+        // Invisible
+        .add_systems(
+            Update,
+            (toggle_fade_delay, fade_stack.after(tick_game_elapsed))
+                .run_if(in_state(GameState::GamePlaying))
+                .run_if(resource_equals(GameMode::Invisible)),
+        )
+        // Countdown
+        .add_systems(OnEnter(GameState::GameCountdown), setup_countdown)
+        .add_systems(
+            Update,
+            tick_countdown.run_if(in_state(GameState::GameCountdown)),
+        )
+        .add_systems(
+            Update,
+            remove_countdown_text.run_if(in_state(GameState::GamePlaying)),
         )
         .add_systems(OnEnter(GameState::GamePaused), setup_game_paused_menu)
         // Game Paused
@@ -106,7 +384,23 @@ fn main() {
         // Game Restarted
         .add_systems(
             OnEnter(GameState::GameRestarted),
-            (clear_game_board, reset_score, reset_lines),
+            (
+                clear_game_board,
+                reset_score,
+                reset_lines,
+                reset_game_timings,
+                reset_garbage_rise_timer,
+                reset_master_state,
+                reset_invisible_stack,
+                setup_puzzle,
+                setup_opener,
+                setup_daily_challenge,
+                setup_generated_board,
+                reset_board_view,
+                setup_versus_boards,
+                clear_placement_hint,
+            )
+                .chain(),
         )
         .add_systems(Update, play_game.run_if(in_state(GameState::GameRestarted)))
         // Common
@@ -0,0 +1,420 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::board::{LinesCleared, PieceLocked};
+use crate::common::*;
+use crate::stats::*;
+use crate::timing::*;
+
+// 大师模式最高等级
+pub const MASTER_MAX_LEVEL: u32 = 999;
+// 每段包含的等级数
+pub const MASTER_SECTION_LEVELS: u32 = 100;
+
+// 重力表：(起始等级, 内部重力值)，内部重力值为每帧下落格数的256倍
+pub const MASTER_GRAVITY_TABLE: [(u32, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, GRAVITY_20G),
+];
+// 20G对应的内部重力值
+const GRAVITY_20G: u32 = 20 * 256;
+
+// 各段延迟（帧）
+#[derive(Debug, Clone, Copy)]
+pub struct MasterSectionDelays {
+    // 起始等级
+    pub level: u32,
+    pub are: u64,
+    pub line_clear_delay: u64,
+    pub lock_delay: u64,
+    pub das: u64,
+}
+
+pub const MASTER_DELAY_TABLE: [MasterSectionDelays; 6] = [
+    MasterSectionDelays {
+        level: 0,
+        are: 25,
+        line_clear_delay: 40,
+        lock_delay: 30,
+        das: 14,
+    },
+    MasterSectionDelays {
+        level: 500,
+        are: 25,
+        line_clear_delay: 25,
+        lock_delay: 30,
+        das: 8,
+    },
+    MasterSectionDelays {
+        level: 600,
+        are: 25,
+        line_clear_delay: 16,
+        lock_delay: 30,
+        das: 8,
+    },
+    MasterSectionDelays {
+        level: 700,
+        are: 16,
+        line_clear_delay: 12,
+        lock_delay: 30,
+        das: 8,
+    },
+    MasterSectionDelays {
+        level: 800,
+        are: 12,
+        line_clear_delay: 6,
+        lock_delay: 30,
+        das: 8,
+    },
+    MasterSectionDelays {
+        level: 900,
+        are: 12,
+        line_clear_delay: 6,
+        lock_delay: 17,
+        das: 6,
+    },
+];
+
+// 段位及所需分数
+pub const MASTER_GRADES: [(&str, u32); 18] = [
+    ("9", 0),
+    ("8", 400),
+    ("7", 800),
+    ("6", 1400),
+    ("5", 2000),
+    ("4", 3500),
+    ("3", 5500),
+    ("2", 8000),
+    ("1", 12000),
+    ("S1", 16000),
+    ("S2", 22000),
+    ("S3", 30000),
+    ("S4", 40000),
+    ("S5", 52000),
+    ("S6", 66000),
+    ("S7", 82000),
+    ("S8", 100000),
+    ("S9", 120000),
+];
+
+// 获得GM段位的条件：(等级, 最低段位序号, 时间上限)
+const GRAND_MASTER_REQUIREMENTS: [(u32, usize, Duration); 3] = [
+    (300, 12, Duration::from_secs(4 * 60 + 15)),
+    (500, 15, Duration::from_secs(7 * 60 + 30)),
+    (MASTER_MAX_LEVEL, 17, Duration::from_secs(13 * 60 + 30)),
+];
+
+// 根据等级计算节奏参数
+pub fn master_timings(level: u32) -> GameTimings {
+    let gravity = MASTER_GRAVITY_TABLE
+        .iter()
+        .rev()
+        .find(|(start, _)| level >= *start)
+        .map(|(_, gravity)| *gravity)
+        .unwrap_or(MASTER_GRAVITY_TABLE[0].1);
+    let delays = MASTER_DELAY_TABLE
+        .iter()
+        .rev()
+        .find(|delays| level >= delays.level)
+        .unwrap_or(&MASTER_DELAY_TABLE[0]);
+    GameTimings {
+        gravity: Some(if gravity >= GRAVITY_20G {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(256.0 / gravity as f64 / 60.0)
+        }),
+        soft_drop: frames(1),
+        are: frames(delays.are),
+        line_clear_delay: frames(delays.line_clear_delay),
+        lock_delay: frames(delays.lock_delay),
+        das: frames(delays.das),
+        arr: frames(1),
+    }
+}
+
+// 大师模式进度
+#[derive(Debug, Resource)]
+pub struct MasterState {
+    pub level: u32,
+    // 游戏总时长
+    pub elapsed: Duration,
+    // 已完成各段的用时
+    pub section_times: Vec<Duration>,
+    // 连击系数
+    pub combo: u32,
+    // 当前段位在MASTER_GRADES中的序号
+    pub grade: usize,
+    // 是否仍满足获得GM的条件
+    pub grand_master_qualified: bool,
+    pub grand_master: bool,
+}
+
+impl Default for MasterState {
+    fn default() -> Self {
+        MasterState {
+            level: 0,
+            elapsed: Duration::ZERO,
+            section_times: Vec::new(),
+            combo: 1,
+            grade: 0,
+            grand_master_qualified: true,
+            grand_master: false,
+        }
+    }
+}
+
+impl MasterState {
+    pub fn grade_name(&self) -> &'static str {
+        if self.grand_master {
+            "GM"
+        } else {
+            MASTER_GRADES[self.grade].0
+        }
+    }
+
+    // 当前段的等级上限
+    pub fn section_stop(&self) -> u32 {
+        ((self.level / MASTER_SECTION_LEVELS + 1) * MASTER_SECTION_LEVELS).min(MASTER_MAX_LEVEL)
+    }
+
+    fn update_grade(&mut self, score: u32) {
+        self.grade = MASTER_GRADES
+            .iter()
+            .rposition(|(_, required)| score >= *required)
+            .unwrap_or(0);
+    }
+
+    fn advance_level(&mut self, amount: u32) {
+        let old_level = self.level;
+        self.level = (self.level + amount).min(MASTER_MAX_LEVEL);
+
+        // 记录段用时
+        if self.level / MASTER_SECTION_LEVELS > old_level / MASTER_SECTION_LEVELS
+            || (self.level == MASTER_MAX_LEVEL && old_level != MASTER_MAX_LEVEL)
+        {
+            let section_start: Duration = self.section_times.iter().sum();
+            let section_time = self.elapsed - section_start;
+            info!(
+                "master section {} finished in {}",
+                self.section_times.len(),
+                format_duration(section_time)
+            );
+            self.section_times.push(section_time);
+        }
+
+        // 检查GM条件
+        for (level, grade, time_limit) in GRAND_MASTER_REQUIREMENTS {
+            if old_level < level
+                && self.level >= level
+                && (self.grade < grade || self.elapsed > time_limit)
+            {
+                self.grand_master_qualified = false;
+            }
+        }
+        if self.level == MASTER_MAX_LEVEL && self.grand_master_qualified {
+            self.grand_master = true;
+        }
+    }
+}
+
+// 格式化为 分:秒.百分秒
+pub fn format_duration(duration: Duration) -> String {
+    let centis = duration.as_millis() / 10;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        centis / 100 % 60,
+        centis % 100
+    )
+}
+
+// 大师模式统计板
+#[derive(Debug, Component)]
+pub struct MasterStatsboard;
+
+#[derive(Debug, Component)]
+pub enum MasterStatsSpan {
+    Level,
+    Grade,
+    Time,
+}
+
+#[derive(Debug, Component)]
+pub struct MasterSectionsboard;
+
+pub fn tick_master_time(time: Res<Time>, mut state: ResMut<MasterState>) {
+    state.elapsed += time.delta();
+}
+
+// 每锁定一个骨牌等级加一（段末x99和998需通过消行升级），每消一行等级加一
+pub fn update_master_level(
+    mut piece_locked_events: EventReader<PieceLocked>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    mut state: ResMut<MasterState>,
+    mut score: ResMut<Score>,
+) {
+    let mut cleared = false;
+    for event in lines_cleared_events.read() {
+        cleared = true;
+        // TGM计分：ceil((等级 + 消行数) / 4) × 消行数 × 连击系数 × 全清奖励
+        state.combo += 2 * event.count - 2;
+        let bravo = if event.perfect_clear { 4 } else { 1 };
+        score.0 += (state.level + event.count).div_ceil(4) * event.count * state.combo * bravo;
+        state.update_grade(score.0);
+        state.advance_level(event.count);
+    }
+    for _ in piece_locked_events.read() {
+        if !cleared {
+            state.combo = 1;
+        }
+        if state.level % MASTER_SECTION_LEVELS != MASTER_SECTION_LEVELS - 1
+            && state.level != MASTER_MAX_LEVEL - 1
+        {
+            state.advance_level(1);
+        }
+    }
+}
+
+// 每段收紧各项延迟
+pub fn update_master_timings(state: Res<MasterState>, mut timings: ResMut<GameTimings>) {
+    let master_timings = master_timings(state.level);
+    if *timings != master_timings {
+        *timings = master_timings;
+    }
+}
+
+// 达到最高等级后结束游戏
+pub fn check_master_finished(
+    state: Res<MasterState>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if state.level >= MASTER_MAX_LEVEL {
+        info!(
+            "master finished, grade: {}, time: {}",
+            state.grade_name(),
+            format_duration(state.elapsed)
+        );
+        app_state.set(AppState::GameOver);
+        game_state.set(GameState::GameQuited);
+    }
+}
+
+pub fn setup_master_board(mut commands: Commands) {
+    let stats_text = |text: &str, span: MasterStatsSpan| {
+        (
+            Text::new(text),
+            TextFont {
+                font_size: 40.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.5, 0.5, 1.0)),
+            Node {
+                height: Val::Px(STATS_BOARD_WIDTH),
+                ..default()
+            },
+            children![(
+                TextSpan::default(),
+                TextFont {
+                    font_size: 40.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(1.0, 0.5, 0.5)),
+                span,
+            )],
+        )
+    };
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        Visibility::Hidden,
+        MasterStatsboard,
+        StatsBoardRow(2),
+        children![
+            stats_text("Level: ", MasterStatsSpan::Level),
+            stats_text("Grade: ", MasterStatsSpan::Grade),
+            stats_text("Time: ", MasterStatsSpan::Time),
+            (
+                Text::default(),
+                TextFont {
+                    font_size: 20.0,
+                    ..default()
+                },
+                TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                MasterSectionsboard,
+            ),
+        ],
+    ));
+}
+
+pub fn update_master_board(
+    game_mode: Res<GameMode>,
+    state: Res<MasterState>,
+    mut q_board: Single<&mut Visibility, With<MasterStatsboard>>,
+    mut q_spans: Query<(&mut TextSpan, &MasterStatsSpan)>,
+    mut q_sections: Single<&mut Text, With<MasterSectionsboard>>,
+) {
+    if *game_mode != GameMode::Master {
+        **q_board = Visibility::Hidden;
+        return;
+    }
+    **q_board = Visibility::Visible;
+    for (mut span, stats_span) in &mut q_spans {
+        **span = match stats_span {
+            MasterStatsSpan::Level => format!("{}/{}", state.level, state.section_stop()),
+            MasterStatsSpan::Grade => state.grade_name().to_string(),
+            MasterStatsSpan::Time => format_duration(state.elapsed),
+        };
+    }
+    // 各段用时
+    q_sections.0 = state
+        .section_times
+        .iter()
+        .enumerate()
+        .map(|(section, time)| {
+            format!(
+                "{:03}-{:03}  {}",
+                section as u32 * MASTER_SECTION_LEVELS,
+                (section as u32 + 1) * MASTER_SECTION_LEVELS - 1,
+                format_duration(*time)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+pub fn reset_master_state(mut state: ResMut<MasterState>) {
+    *state = MasterState::default();
+}
@@ -1,9 +1,15 @@
 use std::sync::LazyLock;
 use bevy::app::AppExit;
 use bevy::color::palettes;
+use bevy::ecs::spawn::SpawnIter;
 use bevy::prelude::*;
 
-use crate::common::{AppState, GameState};
+use crate::board::{BoardSize, BoardSizeSetting};
+use crate::common::{AppState, GameMode, GameState};
+use crate::cpu::{CpuDifficultySetting, cpu_difficulty_label};
+use crate::piece_set::{
+    PieceSet, PieceSetHandles, PieceSetSetting, next_piece_set, piece_set_label,
+};
 
 #[derive(Component)]
 pub struct OnMainMenuScreen;
@@ -16,10 +22,16 @@ pub struct OnGameOverMenuScreen;
 
 #[derive(Component)]
 pub enum MenuButtonAction {
-    StartGame,
+    StartGame(GameMode),
     RestartGame,
     BackToMainMenu,
     ResumeGame,
+    // 切换面板大小
+    CycleBoardSize,
+    // 切换骨牌集
+    CyclePieceSet,
+    // 切换电脑难度
+    CycleCpuDifficulty,
     Quit,
 }
 
@@ -41,18 +53,49 @@ static MENU_BUTTON_CONTAINER_NODE: LazyLock<Node> = LazyLock::new(|| {
     }
 });
 
+// 模式按钮每行三个
+static MENU_MODE_GRID_NODE: LazyLock<Node> = LazyLock::new(|| Node {
+    width: Val::Px(510.0),
+    flex_wrap: FlexWrap::Wrap,
+    justify_content: JustifyContent::Center,
+    ..default()
+});
+
+// 主菜单中的游戏模式
+const MAIN_MENU_MODES: [(&str, GameMode); 19] = [
+    ("Start", GameMode::Marathon),
+    ("Survival", GameMode::Survival),
+    ("Master", GameMode::Master),
+    ("Zen", GameMode::Zen),
+    ("Puzzle", GameMode::Puzzle),
+    ("Invisible", GameMode::Invisible),
+    ("Big", GameMode::Big),
+    ("Daily", GameMode::Daily),
+    ("Finesse", GameMode::Finesse),
+    ("Opener", GameMode::Opener),
+    ("Mirror", GameMode::Mirror),
+    ("Gravity Flip", GameMode::GravityFlip),
+    ("Board Turn", GameMode::BoardTurn),
+    ("Cascade", GameMode::Cascade),
+    ("4-Wide", GameMode::FourWide),
+    ("Cheese", GameMode::Cheese),
+    ("Downstack", GameMode::Downstack),
+    ("Versus", GameMode::Versus),
+    ("Versus CPU", GameMode::VersusCpu),
+];
+
 fn menu_button(text: &str, action: MenuButtonAction) -> impl Bundle {
     (
         Button,
         Node {
-            width: Val::Px(120.0),
+            width: Val::Px(150.0),
             height: Val::Px(30.0),
             margin: UiRect::all(Val::Px(10.0)),
             justify_content: JustifyContent::Center,
             align_items: AlignItems::Center,
             ..default()
         },
-        BackgroundColor(Color::srgb(0.15, 0.15, 0.15).into()),
+        BackgroundColor(Color::srgb(0.15, 0.15, 0.15)),
         action,
         children![(
             Text::new(text),
@@ -65,7 +108,18 @@ fn menu_button(text: &str, action: MenuButtonAction) -> impl Bundle {
     )
 }
 
-pub fn setup_main_menu(mut commands: Commands) {
+fn board_size_label(board_size: &BoardSize) -> String {
+    format!("Board: {}", board_size)
+}
+
+pub fn setup_main_menu(
+    mut commands: Commands,
+    board_size_setting: Res<BoardSizeSetting>,
+    piece_set_setting: Res<PieceSetSetting>,
+    piece_set_handles: Res<PieceSetHandles>,
+    piece_sets: Res<Assets<PieceSet>>,
+    cpu_difficulty_setting: Res<CpuDifficultySetting>,
+) {
     commands.spawn((
         MENU_ROOT_NODE.clone(),
         OnMainMenuScreen,
@@ -86,8 +140,28 @@ pub fn setup_main_menu(mut commands: Commands) {
                         ..default()
                     },
                 ),
-                // 开始按钮
-                menu_button("Start", MenuButtonAction::StartGame),
+                // 各游戏模式按钮
+                (
+                    MENU_MODE_GRID_NODE.clone(),
+                    Children::spawn(SpawnIter(MAIN_MENU_MODES.iter().map(|(text, mode)| {
+                        menu_button(text, MenuButtonAction::StartGame(*mode))
+                    }))),
+                ),
+                // 面板大小按钮
+                menu_button(
+                    &board_size_label(&board_size_setting.0),
+                    MenuButtonAction::CycleBoardSize
+                ),
+                // 骨牌集按钮
+                menu_button(
+                    &piece_set_label(&piece_set_setting, &piece_set_handles, &piece_sets),
+                    MenuButtonAction::CyclePieceSet
+                ),
+                // 电脑难度按钮
+                menu_button(
+                    &cpu_difficulty_label(&cpu_difficulty_setting),
+                    MenuButtonAction::CycleCpuDifficulty
+                ),
                 // 退出按钮
                 menu_button("Quit", MenuButtonAction::Quit),
             ]
@@ -159,42 +233,82 @@ pub fn setup_game_paused_menu(mut commands: Commands) {
 
 pub fn click_button(
     mut interaction_query: Query<
-        (&Interaction, &MenuButtonAction),
+        (&Interaction, &MenuButtonAction, &Children),
         (Changed<Interaction>, With<Button>),
     >,
+    mut q_text: Query<&mut Text>,
     mut app_state: ResMut<NextState<AppState>>,
     mut game_state: ResMut<NextState<GameState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut board_size_setting: ResMut<BoardSizeSetting>,
+    mut piece_set_setting: ResMut<PieceSetSetting>,
+    piece_set_handles: Res<PieceSetHandles>,
+    piece_sets: Res<Assets<PieceSet>>,
+    mut cpu_difficulty_setting: ResMut<CpuDifficultySetting>,
     mut exit: EventWriter<AppExit>,
 ) {
-    for (interaction, menu_button_action) in &mut interaction_query {
-        match *interaction {
-            Interaction::Pressed => match menu_button_action {
-                MenuButtonAction::StartGame => {
-                    info!("StartGame button clicked");
-                    app_state.set(AppState::InGame);
-                    game_state.set(GameState::GamePlaying);
-                }
-                MenuButtonAction::RestartGame => {
-                    info!("RestartGame button clicked");
-                    app_state.set(AppState::InGame);
-                    game_state.set(GameState::GameRestarted);
-                }
-                MenuButtonAction::BackToMainMenu => {
-                    info!("BackToMainMenu button clicked");
-                    println!("{:?}", app_state);
-                    app_state.set(AppState::MainMenu);
-                    game_state.set(GameState::GameQuited);
+    for (interaction, menu_button_action, children) in &mut interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match menu_button_action {
+            MenuButtonAction::StartGame(mode) => {
+                info!("StartGame button clicked, mode: {:?}", mode);
+                *game_mode = *mode;
+                app_state.set(AppState::InGame);
+                game_state.set(GameState::GameCountdown);
+            }
+            MenuButtonAction::RestartGame => {
+                info!("RestartGame button clicked");
+                app_state.set(AppState::InGame);
+                game_state.set(GameState::GameRestarted);
+            }
+            MenuButtonAction::BackToMainMenu => {
+                info!("BackToMainMenu button clicked");
+                println!("{:?}", app_state);
+                app_state.set(AppState::MainMenu);
+                game_state.set(GameState::GameQuited);
+            }
+            MenuButtonAction::ResumeGame => {
+                info!("ResumeGame button clicked");
+                game_state.set(GameState::GamePlaying);
+            }
+            MenuButtonAction::CycleBoardSize => {
+                board_size_setting.0 = board_size_setting.0.next_preset();
+                info!(
+                    "CycleBoardSize button clicked, board size: {}",
+                    board_size_setting.0
+                );
+                for child in children {
+                    if let Ok(mut text) = q_text.get_mut(*child) {
+                        text.0 = board_size_label(&board_size_setting.0);
+                    }
                 }
-                MenuButtonAction::ResumeGame => {
-                    info!("ResumeGame button clicked");
-                    game_state.set(GameState::GamePlaying);
+            }
+            MenuButtonAction::CyclePieceSet => {
+                *piece_set_setting = next_piece_set(&piece_set_setting, &piece_set_handles);
+                let label = piece_set_label(&piece_set_setting, &piece_set_handles, &piece_sets);
+                info!("CyclePieceSet button clicked, {}", label);
+                for child in children {
+                    if let Ok(mut text) = q_text.get_mut(*child) {
+                        text.0 = label.clone();
+                    }
                 }
-                MenuButtonAction::Quit => {
-                    info!("Quit button clicked");
-                    exit.write_default();
+            }
+            MenuButtonAction::CycleCpuDifficulty => {
+                cpu_difficulty_setting.0 = cpu_difficulty_setting.0.next_preset();
+                let label = cpu_difficulty_label(&cpu_difficulty_setting);
+                info!("CycleCpuDifficulty button clicked, {}", label);
+                for child in children {
+                    if let Ok(mut text) = q_text.get_mut(*child) {
+                        text.0 = label.clone();
+                    }
                 }
-            },
-            _ => {}
+            }
+            MenuButtonAction::Quit => {
+                info!("Quit button clicked");
+                exit.write_default();
+            }
         }
     }
 }
@@ -214,8 +328,9 @@ pub fn pause_game(
     }
 }
 
+// 重新开始时与开始游戏一样先倒计时
 pub fn play_game(mut game_state: ResMut<NextState<GameState>>) {
-    game_state.set(GameState::GamePlaying);
+    game_state.set(GameState::GameCountdown);
 }
 
 pub fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
@@ -0,0 +1,421 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::board::*;
+use crate::common::*;
+use crate::piece::*;
+
+// 目标位置提示的透明度
+const CURRENT_TARGET_ALPHA: f32 = 0.5;
+const FUTURE_TARGET_ALPHA: f32 = 0.15;
+// 放错后自动重试前的停顿
+const RETRY_DELAY: f32 = 1.0;
+
+// 步骤编号使用的字符，第i个骨牌对应第i个字符
+const STEP_CHARS: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+// 开局定式
+#[derive(Debug, Clone, Deserialize)]
+pub struct Opener {
+    pub name: String,
+    // 固定的骨牌顺序
+    pub pieces: Vec<PieceType>,
+    // 按标准10x20面板定义的目标形状，自上而下每行一个字符串，'.'表示空格，
+    // 其他字符为步骤编号（0-9、a-z），表示第几个骨牌放置的位置，
+    // 形状按所有骨牌都不消行画出，填满的行在对应骨牌放置后消除，上方的目标随之下移
+    pub board: Vec<String>,
+}
+
+impl Opener {
+    // 每个骨牌在目标形状中的位置，按坐标排序
+    pub fn shape_targets(&self) -> Vec<Vec<Block>> {
+        let mut targets = vec![Vec::new(); self.pieces.len()];
+        for (row, line) in self.board.iter().enumerate() {
+            let y = (self.board.len() - 1 - row) as i32;
+            for (x, c) in line.chars().enumerate() {
+                if c == '.' || c == ' ' {
+                    continue;
+                }
+                match STEP_CHARS.find(c).and_then(|step| targets.get_mut(step)) {
+                    Some(target) => target.push(Block { x: x as i32, y }),
+                    None => warn!("opener {} has unknown step {} in row {}", self.name, c, row),
+                }
+            }
+        }
+        for target in targets.iter_mut() {
+            target.sort_by_key(|block| (block.x, block.y));
+        }
+        targets
+    }
+
+    // 放置前placed个骨牌后已消除的行，按目标形状中的行号
+    pub fn cleared_rows(&self, placed: usize) -> Vec<i32> {
+        let mut row_counts: BTreeMap<i32, i32> = BTreeMap::new();
+        for block in self.shape_targets().iter().take(placed).flatten() {
+            *row_counts.entry(block.y).or_default() += 1;
+        }
+        row_counts
+            .into_iter()
+            .filter(|(_, count)| *count == BoardSize::default().cols)
+            .map(|(row, _)| row)
+            .collect()
+    }
+
+    // 每个骨牌放置时在面板上的目标位置，按坐标排序
+    pub fn targets(&self) -> Vec<Vec<Block>> {
+        self.shape_targets()
+            .iter()
+            .enumerate()
+            .map(|(step, target)| {
+                let cleared_rows = self.cleared_rows(step);
+                target
+                    .iter()
+                    .map(|block| board_position(*block, &cleared_rows))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+// 目标形状中的方块在消除cleared_rows后位于面板上的位置
+pub fn board_position(block: Block, cleared_rows: &[i32]) -> Block {
+    let below = cleared_rows.iter().filter(|row| **row < block.y).count() as i32;
+    Block {
+        x: block.x,
+        y: block.y - below,
+    }
+}
+
+// 定式集，从assets/openers/*.openers.ron加载
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct OpenerSet {
+    pub openers: Vec<Opener>,
+}
+
+impl OpenerSet {
+    // 序号超出时循环
+    pub fn opener(&self, index: usize) -> Option<&Opener> {
+        if self.openers.is_empty() {
+            None
+        } else {
+            Some(&self.openers[index % self.openers.len()])
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct OpenerSetHandle(pub Handle<OpenerSet>);
+
+// 当前定式序号
+#[derive(Debug, Default, Resource)]
+pub struct CurrentOpener(pub usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OpenerStatus {
+    #[default]
+    Playing,
+    Completed,
+    Missed,
+}
+
+// 当前定式进度
+#[derive(Debug, Resource)]
+pub struct OpenerProgress {
+    // 下一个要放置的骨牌序号
+    pub step: usize,
+    // 已消除的行，按目标形状中的行号
+    pub cleared_rows: Vec<i32>,
+    pub status: OpenerStatus,
+    pub retry_timer: Timer,
+}
+
+impl Default for OpenerProgress {
+    fn default() -> Self {
+        OpenerProgress {
+            step: 0,
+            cleared_rows: Vec::new(),
+            status: OpenerStatus::Playing,
+            retry_timer: Timer::from_seconds(RETRY_DELAY, TimerMode::Once),
+        }
+    }
+}
+
+// 目标位置提示方块，不带Block组件，不参与碰撞
+#[derive(Debug, Component)]
+pub struct OpenerTarget {
+    pub step: usize,
+    // 在目标形状中的位置，消行后按已消除的行下移显示
+    pub block: Block,
+}
+
+// 定式名称及进度
+#[derive(Debug, Component)]
+pub struct OpenerInfoboard;
+
+pub fn setup_opener_set(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(OpenerSetHandle(
+        asset_server.load("openers/default.openers.ron"),
+    ));
+}
+
+pub fn setup_opener_infoboard(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        OpenerInfoboard,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
+
+// 开局时注入骨牌序列并生成目标位置提示
+pub fn setup_opener(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
+    opener_set_handle: Res<OpenerSetHandle>,
+    opener_sets: Res<Assets<OpenerSet>>,
+    current_opener: Res<CurrentOpener>,
+    mut progress: ResMut<OpenerProgress>,
+    mut piece_queue: ResMut<PieceQueue>,
+    q_target: Query<Entity, With<OpenerTarget>>,
+) {
+    for entity in &q_target {
+        commands.entity(entity).despawn();
+    }
+    if *game_mode != GameMode::Opener {
+        return;
+    }
+    let Some(opener_set) = opener_sets.get(&opener_set_handle.0) else {
+        warn!("opener set is not loaded yet");
+        return;
+    };
+    let Some(opener) = opener_set.opener(current_opener.0) else {
+        warn!("opener set is empty");
+        return;
+    };
+    info!("setup opener: {}", opener.name);
+    for (step, target) in opener.shape_targets().iter().enumerate() {
+        let color = piece_color(opener.pieces[step]);
+        for block in target {
+            let mut translation = block.translation(&board_size);
+            // 位于骨牌方块下方
+            translation.z = -1.0;
+            commands.spawn((
+                Sprite { color, ..default() },
+                Transform {
+                    scale: Vec3::splat(board_size.sticker_length()),
+                    translation,
+                    ..default()
+                },
+                OpenerTarget {
+                    step,
+                    block: *block,
+                },
+            ));
+        }
+    }
+    piece_queue.0 = opener
+        .pieces
+        .iter()
+        .map(|piece_type| PieceConfig::from_type(*piece_type))
+        .collect::<VecDeque<_>>();
+    *progress = OpenerProgress::default();
+}
+
+// 骨牌锁定时与目标位置比较，需在消行前运行
+pub fn check_opener_placement(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    opener_set_handle: Res<OpenerSetHandle>,
+    opener_sets: Res<Assets<OpenerSet>>,
+    current_opener: Res<CurrentOpener>,
+    mut progress: ResMut<OpenerProgress>,
+    mut piece_queue: ResMut<PieceQueue>,
+    mut piece_locked_events: EventReader<PieceLocked>,
+) {
+    let Some(opener) = opener_sets
+        .get(&opener_set_handle.0)
+        .and_then(|opener_set| opener_set.opener(current_opener.0))
+    else {
+        return;
+    };
+    if progress.status != OpenerStatus::Playing {
+        piece_locked_events.clear();
+        return;
+    }
+    let targets = opener.targets();
+    for event in piece_locked_events.read() {
+        let mut placed = event.blocks.clone();
+        placed.sort_by_key(|block| (block.x, block.y));
+        if targets.get(progress.step) != Some(&placed) {
+            info!("opener missed: {} step {}", opener.name, progress.step + 1);
+            progress.status = OpenerStatus::Missed;
+            commands.spawn(AudioPlayer(game_audios.gameover.clone()));
+            piece_queue.0.clear();
+            break;
+        }
+        progress.step += 1;
+        progress.cleared_rows = opener.cleared_rows(progress.step);
+        if progress.step == targets.len() {
+            info!("opener completed: {}", opener.name);
+            progress.status = OpenerStatus::Completed;
+            commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+            break;
+        }
+    }
+}
+
+// 放错后自动重试，R键立即重试，Tab键切换下一个定式
+pub fn handle_opener_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut progress: ResMut<OpenerProgress>,
+    mut current_opener: ResMut<CurrentOpener>,
+    mut game_state: ResMut<NextState<GameState>>,
+    time: Res<Time>,
+) {
+    if progress.status == OpenerStatus::Missed {
+        progress.retry_timer.tick(time.delta());
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        current_opener.0 += 1;
+        game_state.set(GameState::GameRestarted);
+    } else if keyboard_input.just_pressed(KeyCode::KeyR) || progress.retry_timer.finished() {
+        game_state.set(GameState::GameRestarted);
+    }
+}
+
+// 已放置的目标隐藏，当前骨牌的目标比之后的更明显，消行后未放置的目标随之下移
+pub fn update_opener_targets(
+    progress: Res<OpenerProgress>,
+    board_size: Res<BoardSize>,
+    mut q_target: Query<(&OpenerTarget, &mut Sprite, &mut Transform, &mut Visibility)>,
+) {
+    if !progress.is_changed() {
+        return;
+    }
+    for (target, mut sprite, mut transform, mut visibility) in &mut q_target {
+        let block = board_position(target.block, &progress.cleared_rows);
+        let translation = block.translation(&board_size);
+        transform.translation.x = translation.x;
+        transform.translation.y = translation.y;
+        *visibility = if target.step < progress.step {
+            Visibility::Hidden
+        } else {
+            Visibility::Visible
+        };
+        let alpha = if target.step == progress.step {
+            CURRENT_TARGET_ALPHA
+        } else {
+            FUTURE_TARGET_ALPHA
+        };
+        sprite.color.set_alpha(alpha);
+    }
+}
+
+pub fn update_opener_infoboard(
+    game_mode: Res<GameMode>,
+    opener_set_handle: Res<OpenerSetHandle>,
+    opener_sets: Res<Assets<OpenerSet>>,
+    current_opener: Res<CurrentOpener>,
+    progress: Res<OpenerProgress>,
+    q_infoboard: Single<(&mut Visibility, &Children), With<OpenerInfoboard>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let (mut visibility, children) = q_infoboard.into_inner();
+    let opener_set = opener_sets.get(&opener_set_handle.0);
+    let (Some(opener_set), GameMode::Opener) = (opener_set, *game_mode) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    let Some(opener) = opener_set.opener(current_opener.0) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+
+    let mut info = format!(
+        "Opener {}/{} {}: {}/{} pieces",
+        current_opener.0 % opener_set.openers.len() + 1,
+        opener_set.openers.len(),
+        opener.name,
+        progress.step,
+        opener.pieces.len()
+    );
+    match progress.status {
+        OpenerStatus::Playing => info.push_str("\nTab: next opener, R: retry"),
+        OpenerStatus::Completed => info.push_str("\nCompleted! Tab: next opener, R: retry"),
+        OpenerStatus::Missed => info.push_str("\nMissed! Retrying..."),
+    }
+    for child in children {
+        if let Ok(mut text) = q_text.get_mut(*child)
+            && text.0 != info
+        {
+            text.0 = info.clone();
+        }
+    }
+}
+
+pub fn reset_current_opener(mut current_opener: ResMut<CurrentOpener>) {
+    current_opener.0 = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{placements, simulate};
+    use crate::engine::Game;
+
+    // 按游戏的旋转和锁定规则依次放置每个骨牌，每一步都必须能到达目标位置
+    #[test]
+    fn openers_are_reachable() {
+        let opener_set: OpenerSet =
+            ron::from_str(include_str!("../assets/openers/default.openers.ron"))
+                .expect("valid opener set");
+        for opener in &opener_set.openers {
+            let targets = opener.targets();
+            let mut game = Game::new(BoardSize::default(), 0);
+            game.queue = opener.pieces.iter().copied().collect();
+            for (step, target) in targets.iter().enumerate() {
+                assert!(
+                    game.spawn_piece(),
+                    "{} step {} cannot spawn",
+                    opener.name,
+                    step
+                );
+                let mut target = target.clone();
+                target.sort_by_key(|block| (block.y, block.x));
+                let clears = opener.cleared_rows(step + 1).len() - opener.cleared_rows(step).len();
+                // 消行的T骨牌需要以T-spin放入
+                let t_spin = clears > 0 && opener.pieces[step] == PieceType::T;
+                let placement = placements(&game)
+                    .into_iter()
+                    .find(|placement| placement.blocks == target && placement.t_spin == t_spin)
+                    .unwrap_or_else(|| panic!("{} step {} is unreachable", opener.name, step));
+                let (after, lines_cleared) = simulate(&game, &placement);
+                let count = lines_cleared.map_or(0, |lines_cleared| lines_cleared.count);
+                assert_eq!(
+                    count as usize, clears,
+                    "{} step {} clears {}",
+                    opener.name, step, count
+                );
+                game = after;
+            }
+        }
+    }
+}
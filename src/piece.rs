@@ -1,9 +1,19 @@
 use bevy::color::palettes;
-use std::collections::{BTreeSet, VecDeque};
-
-use crate::{board::*, common::GameAudios};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::{
+    board::*,
+    common::{GameAudios, GameMode},
+    piece_set::{ActivePieceSet, random_bag, rotate_blocks},
+    timing::*,
+    variant::BoardView,
+};
 use bevy::prelude::*;
-use rand::Rng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 const SHAPE_I: [[i32; 2]; 4] = [[3, 0], [4, 0], [5, 0], [6, 0]];
 const SHAPE_J: [[i32; 2]; 4] = [[3, 1], [3, 0], [4, 0], [5, 0]];
@@ -13,63 +23,41 @@ const SHAPE_S: [[i32; 2]; 4] = [[3, 0], [4, 0], [4, 1], [5, 1]];
 const SHAPE_T: [[i32; 2]; 4] = [[3, 0], [4, 1], [4, 0], [5, 0]];
 const SHAPE_Z: [[i32; 2]; 4] = [[3, 1], [4, 1], [4, 0], [5, 0]];
 
-pub fn piece_shape(piece_type: PieceType) -> [Block; 4] {
-    match piece_type {
-        PieceType::I => SHAPE_I.map(|pos| pos.into()),
-        PieceType::J => SHAPE_J.map(|pos| pos.into()),
-        PieceType::L => SHAPE_L.map(|pos| pos.into()),
-        PieceType::O => SHAPE_O.map(|pos| pos.into()),
-        PieceType::S => SHAPE_S.map(|pos| pos.into()),
-        PieceType::T => SHAPE_T.map(|pos| pos.into()),
-        PieceType::Z => SHAPE_Z.map(|pos| pos.into()),
-    }
+// 标准四格骨牌的形状，自定义骨牌的形状由骨牌集定义
+pub fn piece_shape(piece_type: PieceType) -> Vec<Block> {
+    let shape = match piece_type {
+        PieceType::I => SHAPE_I,
+        PieceType::J => SHAPE_J,
+        PieceType::L => SHAPE_L,
+        PieceType::O => SHAPE_O,
+        PieceType::S => SHAPE_S,
+        PieceType::T => SHAPE_T,
+        PieceType::Z => SHAPE_Z,
+        PieceType::Custom(_) => return Vec::new(),
+    };
+    shape.iter().map(|pos| (*pos).into()).collect()
 }
 
 // 平移骨牌
-pub fn shift_piece(
-    mut blocks: [Block; 4],
-    delta_x: Option<i32>,
-    delta_y: Option<i32>,
-) -> [Block; 4] {
-    match delta_x {
-        Some(delta) => {
-            blocks[0].x += delta;
-            blocks[1].x += delta;
-            blocks[2].x += delta;
-            blocks[3].x += delta;
-        }
-        None => {}
-    }
-    match delta_y {
-        Some(delta) => {
-            blocks[0].y += delta;
-            blocks[1].y += delta;
-            blocks[2].y += delta;
-            blocks[3].y += delta;
-        }
-        None => {}
-    }
+pub fn shift_piece(blocks: &[Block], delta_x: Option<i32>, delta_y: Option<i32>) -> Vec<Block> {
     blocks
+        .iter()
+        .map(|block| shift_block(*block, delta_x, delta_y))
+        .collect()
 }
 
-fn shift_block(mut block: Block, delta_x: Option<i32>, delta_y: Option<i32>) -> Block {
-    match delta_x {
-        Some(delta) => {
-            block.x += delta;
-        }
-        None => {}
+pub fn shift_block(mut block: Block, delta_x: Option<i32>, delta_y: Option<i32>) -> Block {
+    if let Some(delta) = delta_x {
+        block.x += delta;
     }
-    match delta_y {
-        Some(delta) => {
-            block.y += delta;
-        }
-        None => {}
+    if let Some(delta) = delta_y {
+        block.y += delta;
     }
     block
 }
 
 // 四格骨牌
-#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
 pub enum PieceType {
     // ####
     I,
@@ -97,38 +85,65 @@ pub enum PieceType {
     // ##
     //  ##
     Z,
+
+    // 自定义骨牌，序号对应当前骨牌集中的骨牌
+    Custom(u8),
 }
 
 impl PieceType {
     pub const PIECE_AMOUNT: u32 = 7;
+    pub const ALL: [PieceType; 7] = [
+        PieceType::I,
+        PieceType::J,
+        PieceType::L,
+        PieceType::O,
+        PieceType::S,
+        PieceType::T,
+        PieceType::Z,
+    ];
+}
+
+pub fn piece_color(piece_type: PieceType) -> Color {
+    Color::Srgba(match piece_type {
+        PieceType::I => palettes::css::LIGHT_CYAN,
+        PieceType::J => palettes::css::BLUE,
+        PieceType::L => palettes::css::ORANGE,
+        PieceType::O => palettes::css::YELLOW,
+        PieceType::S => palettes::css::GREEN,
+        PieceType::T => palettes::css::PURPLE,
+        PieceType::Z => palettes::css::RED,
+        // 自定义骨牌的颜色由骨牌集定义
+        PieceType::Custom(_) => palettes::css::WHITE,
+    })
 }
 
 #[derive(Debug, Clone)]
 pub struct PieceConfig {
     pub piece_type: PieceType,
-    pub blocks: [Block; 4],
+    pub blocks: Vec<Block>,
     pub color: Color,
 }
 
 impl PieceConfig {
-    pub fn new(piece_type: PieceType, blocks: [Block; 4]) -> Self {
-        let color = Color::Srgba(match piece_type {
-            PieceType::I => palettes::css::LIGHT_CYAN,
-            PieceType::J => palettes::css::BLUE,
-            PieceType::L => palettes::css::ORANGE,
-            PieceType::O => palettes::css::YELLOW,
-            PieceType::S => palettes::css::GREEN,
-            PieceType::T => palettes::css::PURPLE,
-            PieceType::Z => palettes::css::RED,
-        });
+    pub fn new(piece_type: PieceType, blocks: Vec<Block>) -> Self {
+        let color = piece_color(piece_type);
         PieceConfig {
             piece_type,
             blocks,
             color,
         }
     }
+
+    // 标准形状的骨牌，生成时再根据面板大小平移到出生位置
+    pub fn from_type(piece_type: PieceType) -> Self {
+        PieceConfig::new(piece_type, piece_shape(piece_type))
+    }
 }
 
+// 骨牌当前的旋转状态，出生时为0
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct RotationState(pub usize);
+
 // 可移动方向
 #[derive(Component)]
 pub struct Movable {
@@ -137,85 +152,241 @@ pub struct Movable {
     pub can_right: bool,
 }
 
-// 自动向下移动四格骨牌计时器
-#[derive(Debug, Resource)]
-pub struct AutoMovePieceDownTimer(pub Timer);
-
-pub const NORMAL_MOVE_DURATION: std::time::Duration = std::time::Duration::from_millis(1000);
-pub const FAST_MOVE_DURATION: std::time::Duration = std::time::Duration::from_millis(10);
-
 // 待生成的骨牌队列
 #[derive(Debug, Resource)]
 pub struct PieceQueue(pub VecDeque<PieceConfig>);
 
-// 控制手动移动频率
+// 生成骨牌序列的随机数生成器，每日挑战使用固定种子
 #[derive(Debug, Resource)]
-pub struct ManuallyMoveTimer(pub Timer);
+pub struct PieceRng(pub ChaCha8Rng);
+
+impl Default for PieceRng {
+    fn default() -> Self {
+        PieceRng(ChaCha8Rng::from_os_rng())
+    }
+}
+
+// 玩家对当前骨牌的操作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PieceInput {
+    Left,
+    Right,
+    // 长按移动到底
+    DasLeft,
+    DasRight,
+    Rotate,
+    SoftDrop,
+    HardDrop,
+}
+
+// 当前骨牌已执行的操作，生成新骨牌时清空
+#[derive(Debug, Default, Resource)]
+pub struct PieceInputs(pub Vec<PieceInput>);
+
+// 下一个骨牌出生时是否立即旋转（IRS），开局倒计时结束时按住旋转键即生效
+#[derive(Debug, Default, Resource)]
+pub struct InitialRotation(pub bool);
+
+// 骨牌最后一次成功的操作是否为旋转，用于判断T-spin
+#[derive(Debug, Default, Resource)]
+pub struct LastMoveIsRotation(pub bool);
 
-pub fn setup_piece_queue(mut commands: Commands) {
+pub fn setup_piece_queue(mut commands: Commands, mut piece_rng: ResMut<PieceRng>) {
     let mut piece_queue = PieceQueue(VecDeque::new());
-    piece_queue.0.extend(random_7_pieces());
+    piece_queue.0.extend(random_7_pieces(&mut piece_rng.0));
     commands.insert_resource(piece_queue);
 }
 
+// 面板镜像或旋转时，左右按键按屏幕方向移动
+pub fn horizontal_keys(board_view: &BoardView) -> (KeyCode, KeyCode) {
+    if board_view.flips_x() {
+        (KeyCode::ArrowRight, KeyCode::ArrowLeft)
+    } else {
+        (KeyCode::ArrowLeft, KeyCode::ArrowRight)
+    }
+}
+
+// 左右移动：按下时立即移动一格，长按超过DAS后每隔ARR移动一格
+// 返回本帧移动的格数及DAS是否刚好蓄满
+pub fn tick_das(
+    keyboard_input: &ButtonInput<KeyCode>,
+    (left_key, right_key): (KeyCode, KeyCode),
+    timers: &mut GameTimers,
+    delta: Duration,
+) -> (i32, bool) {
+    let holding_left = keyboard_input.pressed(left_key);
+    let holding_right = keyboard_input.pressed(right_key);
+    if keyboard_input.just_pressed(left_key) {
+        timers.das.reset();
+        timers.arr.reset();
+        return (-1, false);
+    }
+    if keyboard_input.just_pressed(right_key) {
+        timers.das.reset();
+        timers.arr.reset();
+        return (1, false);
+    }
+    if !holding_left && !holding_right {
+        return (0, false);
+    }
+    timers.das.tick(delta);
+    if !timers.das.finished() {
+        return (0, false);
+    }
+    timers.arr.tick(delta);
+    let delta_x = if timers.das.just_finished() || timers.arr.just_finished() {
+        if holding_left { -1 } else { 1 }
+    } else {
+        0
+    };
+    (delta_x, timers.das.just_finished())
+}
+
+// 记录左右移动操作，长按触发DAS时将本次按键记为长按
+// DAS在出块延迟中就已蓄满时，新骨牌出生后会直接长按移动，同样记为一次长按
+pub fn record_shift_input(
+    keyboard_input: &ButtonInput<KeyCode>,
+    (left_key, right_key): (KeyCode, KeyCode),
+    timers: &GameTimers,
+    das_charged: bool,
+    piece_inputs: &mut PieceInputs,
+) {
+    let holding_left = keyboard_input.pressed(left_key);
+    let holding = holding_left || keyboard_input.pressed(right_key);
+    let shifted = piece_inputs.0.iter().any(|input| {
+        matches!(
+            input,
+            PieceInput::Left | PieceInput::Right | PieceInput::DasLeft | PieceInput::DasRight
+        )
+    });
+    let das_carried = holding && timers.das.finished() && !shifted;
+    if keyboard_input.just_pressed(left_key) {
+        piece_inputs.0.push(PieceInput::Left);
+    } else if keyboard_input.just_pressed(right_key) {
+        piece_inputs.0.push(PieceInput::Right);
+    } else if das_charged || das_carried {
+        let (tap_input, das_input) = if holding_left {
+            (PieceInput::Left, PieceInput::DasLeft)
+        } else {
+            (PieceInput::Right, PieceInput::DasRight)
+        };
+        match piece_inputs.0.last_mut() {
+            Some(last) if *last == tap_input => *last = das_input,
+            _ => piece_inputs.0.push(das_input),
+        }
+    }
+}
+
 // 自动和手动移动四格骨牌
 pub fn move_piece(
     mut commands: Commands,
     game_audios: Res<GameAudios>,
-    mut query: Query<(&mut Block, &mut Transform, &Movable), With<PieceType>>,
+    mut query: Query<(&mut Block, &mut Transform), With<PieceType>>,
+    q_board: Query<&Block, Without<PieceType>>,
+    board_size: Res<BoardSize>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut manually_move_timer: ResMut<ManuallyMoveTimer>,
-    mut auto_move_timer: ResMut<AutoMovePieceDownTimer>,
+    timings: Res<GameTimings>,
+    mut timers: ResMut<GameTimers>,
+    board_view: Res<BoardView>,
+    mut piece_inputs: ResMut<PieceInputs>,
+    mut last_move_is_rotation: ResMut<LastMoveIsRotation>,
     time: Res<Time>,
 ) {
-    manually_move_timer.0.tick(time.delta());
-    auto_move_timer.0.tick(time.delta());
+    let (left_key, right_key) = horizontal_keys(&board_view);
+    // 没有骨牌时（出块延迟中）也可以提前蓄力DAS
+    let (delta_x, das_charged) = tick_das(
+        &keyboard_input,
+        (left_key, right_key),
+        &mut timers,
+        time.delta(),
+    );
 
-    if keyboard_input.pressed(KeyCode::Space) && auto_move_timer.0.duration() != FAST_MOVE_DURATION
-    {
-        auto_move_timer.0.set_duration(FAST_MOVE_DURATION);
+    if query.is_empty() {
+        return;
+    }
+    record_shift_input(
+        &keyboard_input,
+        (left_key, right_key),
+        &timers,
+        das_charged,
+        &mut piece_inputs,
+    );
+    if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+        piece_inputs.0.push(PieceInput::SoftDrop);
+    }
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        piece_inputs.0.push(PieceInput::HardDrop);
+    }
+    let board: Vec<Block> = q_board.iter().copied().collect();
+    let mut piece: Vec<Block> = query.iter().map(|(block, _)| *block).collect();
+    let mut play_audio = false;
+
+    // 手动左右移动
+    if delta_x != 0 {
+        let shifted: Vec<Block> = piece
+            .iter()
+            .map(|block| shift_block(*block, Some(delta_x), None))
+            .collect();
+        if !is_overlapping(&shifted, &board, &board_size) {
+            piece = shifted;
+            play_audio = true;
+            last_move_is_rotation.0 = false;
+        }
     }
 
-    let mut reset_manually_move_timer = false;
-    for (mut block, mut transform, movable) in &mut query {
-        // 防止一帧中向下移动两行
-        let mut already_down = false;
-        // 自动下移
-        if auto_move_timer.0.just_finished() && movable.can_down {
-            block.y -= 1;
-
-            if auto_move_timer.0.duration() != FAST_MOVE_DURATION {
-                spawn_drop_audio(&mut commands, &game_audios);
+    // 向下移动
+    let max_drop = drop_distance(&piece, &board, &board_size);
+    let delta_y = if keyboard_input.just_pressed(KeyCode::Space) {
+        // 硬降：直接落到底部并立即锁定
+        finish_timer(&mut timers.lock);
+        play_audio = true;
+        max_drop
+    } else {
+        // 自动下落，重力为零时直接落到底部（20G）
+        let gravity_rows = match timings.gravity {
+            None => 0,
+            Some(gravity) if gravity.is_zero() => max_drop,
+            Some(_) => {
+                timers.gravity.tick(time.delta());
+                timers.gravity.times_finished_this_tick() as i32
             }
-            already_down = true;
+        };
+        // 软降
+        let mut soft_drop_rows = 0;
+        if keyboard_input.just_pressed(KeyCode::ArrowDown) {
+            timers.soft_drop.reset();
+            soft_drop_rows = 1;
+        } else if keyboard_input.pressed(KeyCode::ArrowDown) {
+            timers.soft_drop.tick(time.delta());
+            soft_drop_rows = timers.soft_drop.times_finished_this_tick() as i32;
         }
-        // 手动移动
-        if manually_move_timer.0.finished() {
-            if keyboard_input.pressed(KeyCode::ArrowLeft) && movable.can_left {
-                block.x -= 1;
-                spawn_drop_audio(&mut commands, &game_audios);
-                reset_manually_move_timer = true;
-            } else if keyboard_input.pressed(KeyCode::ArrowRight) && movable.can_right {
-                block.x += 1;
-                spawn_drop_audio(&mut commands, &game_audios);
-                reset_manually_move_timer = true;
-            } else if keyboard_input.pressed(KeyCode::ArrowDown)
-                && movable.can_down
-                && !already_down
-            {
-                block.y -= 1;
-                spawn_drop_audio(&mut commands, &game_audios);
-                reset_manually_move_timer = true;
-            }
+        let rows = gravity_rows.max(soft_drop_rows).min(max_drop);
+        // 20G下落时不播放音效
+        if rows > 0 && (soft_drop_rows > 0 || timings.gravity != Some(Duration::ZERO)) {
+            play_audio = true;
         }
-        transform.translation = block.translation();
+        rows
+    };
+    if delta_y > 0 {
+        last_move_is_rotation.0 = false;
+        piece = piece
+            .iter()
+            .map(|block| shift_block(*block, None, Some(-delta_y)))
+            .collect();
+    }
+
+    if play_audio {
+        spawn_drop_audio(&mut commands, &game_audios);
     }
-    if reset_manually_move_timer {
-        manually_move_timer.0.reset();
+    for (index, (mut block, mut transform)) in query.iter_mut().enumerate() {
+        if *block != piece[index] {
+            *block = piece[index];
+            transform.translation = block.translation(&board_size);
+        }
     }
 }
 
-fn spawn_drop_audio(commands: &mut Commands, game_audios: &Res<GameAudios>) {
+pub fn spawn_drop_audio(commands: &mut Commands, game_audios: &Res<GameAudios>) {
     commands.spawn((
         AudioPlayer(game_audios.drop.clone()),
         PlaybackSettings::DESPAWN,
@@ -226,7 +397,7 @@ fn spawn_drop_audio(commands: &mut Commands, game_audios: &Res<GameAudios>) {
 pub fn check_collision(
     mut piece_query: Query<(&mut Block, &mut Movable), With<PieceType>>,
     board_query: Query<&Block, Without<PieceType>>,
-    mut auto_move_timer: ResMut<AutoMovePieceDownTimer>,
+    board_size: Res<BoardSize>,
 ) {
     let mut can_down = true;
     let mut can_left = true;
@@ -238,7 +409,7 @@ pub fn check_collision(
             // 碰撞左边界
             can_left = false;
         }
-        if block.x == 9 {
+        if block.x == board_size.cols - 1 {
             // 碰撞右边界
             can_right = false;
         }
@@ -273,128 +444,171 @@ pub fn check_collision(
         movable.can_right = can_right;
         movable.can_down = can_down;
     }
-    if !can_down {
-        auto_move_timer.0.set_duration(NORMAL_MOVE_DURATION);
-    }
 }
 
 pub fn rotate_piece(
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut q_piece: Query<(&mut PieceType, &mut Block, &mut Transform)>,
+    mut q_piece: Query<(&PieceType, &mut Block, &mut Transform, &mut RotationState)>,
     q_board: Query<&Block, Without<PieceType>>,
+    board_size: Res<BoardSize>,
+    active_piece_set: Res<ActivePieceSet>,
+    mut piece_inputs: ResMut<PieceInputs>,
+    mut last_move_is_rotation: ResMut<LastMoveIsRotation>,
+    mut initial_rotation: ResMut<InitialRotation>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::ArrowUp) {
-        let piece_type = match q_piece.iter().next() {
-            Some((piece_type, _, _)) => piece_type.clone(),
-            None => {
-                return;
-            }
+    let initial = initial_rotation.0 && !q_piece.is_empty();
+    if keyboard_input.just_pressed(KeyCode::ArrowUp) || initial {
+        let Some((piece_type, _, _, rotation_state)) = q_piece.iter().next() else {
+            return;
         };
-        let sum_x = q_piece.iter().map(|(_, block, _)| block.x).sum::<i32>();
-        let sum_y = q_piece.iter().map(|(_, block, _)| block.y).sum::<i32>();
-
-        let original_blocks: Vec<Block> =
-            q_piece.iter().map(|(_, block, _)| block.clone()).collect();
-        // 通过矩阵变化实现旋转，可以理解为沿y=x对称后沿y=0对称，然后平移
-        for (_, mut block, mut transform) in &mut q_piece {
-            *block = match piece_type {
-                // 微调平移量，使其更自然
-                PieceType::O | PieceType::L | PieceType::J => shift_block(
-                    [block.y, -block.x].into(),
-                    Some(sum_x / 4 - sum_y / 4),
-                    Some(sum_x / 4 + sum_y / 4 + 1),
-                ),
-                _ => shift_block(
-                    [block.y, -block.x].into(),
-                    Some(sum_x / 4 - sum_y / 4),
-                    Some(sum_x / 4 + sum_y / 4),
-                ),
-            };
-            transform.translation = block.translation();
-        }
+        initial_rotation.0 = false;
+        let (piece_type, state) = (*piece_type, rotation_state.0);
+        piece_inputs.0.push(PieceInput::Rotate);
 
-        // 当出现碰撞时，尝试左右平移最多2格（也可采取旋转后一旦出现碰撞则恢复原样）
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut transform) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(-1), None);
-                transform.translation = block.translation();
-            }
-        }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut transform) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(-1), None);
-                transform.translation = block.translation();
-            }
-        }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut transform) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(3), None);
-                transform.translation = block.translation();
-            }
-        }
-        if whether_colliding(&q_piece, &q_board) {
-            for (_, mut block, mut transform) in &mut q_piece {
-                *block = shift_block(block.clone(), Some(3), None);
-                transform.translation = block.translation();
-            }
-        }
-        // 恢复旋转前样子
-        if whether_colliding(&q_piece, &q_board) {
-            let mut index = 0;
-            for (_, mut block, mut transform) in &mut q_piece {
-                *block = original_blocks[index];
-                transform.translation = block.translation();
-                index += 1;
-            }
+        let piece: Vec<Block> = q_piece.iter().map(|(_, block, _, _)| *block).collect();
+        let board: Vec<Block> = q_board.iter().copied().collect();
+        let Some((rotated, next_state)) = rotate_blocks(
+            piece_type,
+            state,
+            &piece,
+            &board,
+            &board_size,
+            &active_piece_set,
+        ) else {
+            return;
+        };
+        for (index, (_, mut block, mut transform, mut rotation_state)) in
+            q_piece.iter_mut().enumerate()
+        {
+            *block = rotated[index];
+            transform.translation = block.translation(&board_size);
+            rotation_state.0 = next_state;
         }
+        last_move_is_rotation.0 = true;
     }
 }
 
-// 检测旋转过程中是否发送碰撞
-pub fn whether_colliding(
-    piece_query: &Query<(&mut PieceType, &mut Block, &mut Transform)>,
-    board_query: &Query<&Block, Without<PieceType>>,
-) -> bool {
-    // 检查是否碰撞边界
-    for (_, block, _) in piece_query {
-        if block.x < 0 {
-            // 碰撞左边界
-            return true;
-        }
-        if block.x > 9 {
-            // 碰撞右边界
-            return true;
-        }
-        if block.y < 0 {
-            // 碰撞下边界
-            return true;
-        }
+// SRS顺时针旋转的踢墙表，下标为旋转前的状态，依次尝试各个平移
+const SRS_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+const SRS_I_KICKS: [[(i32, i32); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+// 标准骨牌绕其旋转的方框边长，以及出生形状所在方框的左下角
+fn rotation_box(piece_type: PieceType) -> (i32, Block) {
+    match piece_type {
+        PieceType::I => (4, Block { x: 3, y: -2 }),
+        PieceType::O => (2, Block { x: 4, y: 0 }),
+        _ => (3, Block { x: 3, y: -1 }),
     }
+}
 
-    // 检查是否碰撞面板方块
-    for (_, block, _) in piece_query {
-        for board_block in board_query {
-            if board_block.y == block.y && block.x > 0 && board_block.x == block.x - 1 {
-                // 防止0-1溢出
-                // 左侧碰撞
-                return true;
-            }
-            if board_block.y == block.y && board_block.x == block.x + 1 {
-                // 右侧碰撞
-                return true;
-            }
-            if board_block.x == block.x && block.y > 0 && board_block.y == block.y - 1 {
-                // 下侧碰撞
-                return true;
-            }
-        }
+// 在边长为size的方框内顺时针旋转90度
+fn rotate_in_box(block: Block, size: i32) -> Block {
+    Block {
+        x: block.y,
+        y: size - 1 - block.x,
     }
-    return false;
 }
 
-pub fn control_piece_visibility(mut q_piece: Query<(&mut Visibility, &Block), With<PieceType>>) {
+// 按SRS顺时针旋转标准四格骨牌，state为旋转前的状态，无法旋转时返回None
+// 方块顺序与旋转前保持一致
+pub fn rotate_standard(
+    piece_type: PieceType,
+    state: usize,
+    piece: &[Block],
+    board: &[Block],
+    board_size: &BoardSize,
+) -> Option<Vec<Block>> {
+    let (size, spawn_origin) = rotation_box(piece_type);
+    // 由当前状态下骨牌在方框中的位置推算方框的左下角
+    let cells: Vec<Block> = piece_shape(piece_type)
+        .iter()
+        .map(|block| {
+            let cell = shift_block(*block, Some(-spawn_origin.x), Some(-spawn_origin.y));
+            (0..state % 4).fold(cell, |cell, _| rotate_in_box(cell, size))
+        })
+        .collect();
+    let min = |blocks: &[Block]| {
+        (
+            blocks.iter().map(|block| block.x).min().unwrap_or_default(),
+            blocks.iter().map(|block| block.y).min().unwrap_or_default(),
+        )
+    };
+    let ((piece_x, piece_y), (cell_x, cell_y)) = (min(piece), min(&cells));
+    let origin = Block {
+        x: piece_x - cell_x,
+        y: piece_y - cell_y,
+    };
+    let rotated: Vec<Block> = piece
+        .iter()
+        .map(|block| {
+            let cell = shift_block(*block, Some(-origin.x), Some(-origin.y));
+            shift_block(rotate_in_box(cell, size), Some(origin.x), Some(origin.y))
+        })
+        .collect();
+
+    let kicks = match piece_type {
+        PieceType::O => &[(0, 0)][..],
+        PieceType::I => &SRS_I_KICKS[state % 4][..],
+        _ => &SRS_KICKS[state % 4][..],
+    };
+    kicks
+        .iter()
+        .map(|(delta_x, delta_y)| shift_piece(&rotated, Some(*delta_x), Some(*delta_y)))
+        .find(|kicked| !is_overlapping(kicked, board, board_size))
+}
+
+// 锁定的骨牌是否为T-spin：T骨牌最后一次成功的操作为旋转，且满足三角规则
+pub fn is_lock_t_spin(
+    piece_type: PieceType,
+    last_move_is_rotation: bool,
+    piece: &[Block],
+    board: &[Block],
+    board_size: &BoardSize,
+) -> bool {
+    piece_type == PieceType::T && last_move_is_rotation && is_t_spin(piece, board, board_size)
+}
+
+// 按三角规则判断T-spin：T骨牌中心四个对角中至少三个被占据（或在边界外）
+pub fn is_t_spin(piece: &[Block], board: &[Block], board_size: &BoardSize) -> bool {
+    // 中心方块与其余三个方块均相邻
+    let Some(center) = piece.iter().find(|block| {
+        piece
+            .iter()
+            .filter(|other| (other.x - block.x).abs() + (other.y - block.y).abs() == 1)
+            .count()
+            == 3
+    }) else {
+        return false;
+    };
+    [(-1, -1), (-1, 1), (1, -1), (1, 1)]
+        .iter()
+        .filter(|(dx, dy)| {
+            let corner = Block {
+                x: center.x + dx,
+                y: center.y + dy,
+            };
+            is_overlapping(&[corner], board, board_size)
+        })
+        .count()
+        >= 3
+}
+
+pub fn control_piece_visibility(
+    mut q_piece: Query<(&mut Visibility, &Block), With<PieceType>>,
+    board_size: Res<BoardSize>,
+) {
     for (mut visibility, block) in &mut q_piece {
-        if block.y > 19 {
+        if block.y >= board_size.rows {
             *visibility = Visibility::Hidden;
         } else {
             *visibility = Visibility::Visible;
@@ -406,23 +620,53 @@ pub fn control_piece_visibility(mut q_piece: Query<(&mut Visibility, &Block), Wi
 pub fn auto_generate_new_piece(
     mut commands: Commands,
     query: Query<&PieceType>,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
     mut piece_queue: ResMut<PieceQueue>,
+    mut piece_rng: ResMut<PieceRng>,
+    active_piece_set: Res<ActivePieceSet>,
+    mut timers: ResMut<GameTimers>,
+    mut piece_inputs: ResMut<PieceInputs>,
+    mut last_move_is_rotation: ResMut<LastMoveIsRotation>,
+    time: Res<Time>,
 ) {
-    if piece_queue.0.len() < PieceType::PIECE_AMOUNT as usize {
-        piece_queue.0.extend(random_7_pieces());
+    // 谜题模式和定式练习使用固定的骨牌序列
+    if !matches!(*game_mode, GameMode::Puzzle | GameMode::Opener)
+        && piece_queue.0.len() < PieceType::PIECE_AMOUNT as usize
+    {
+        piece_queue
+            .0
+            .extend(random_bag(&mut piece_rng.0, &active_piece_set));
     }
     if query.is_empty() {
-        let piece_config = piece_queue.0.pop_front().unwrap();
+        // 等待消行延迟和出块延迟（ARE）结束
+        timers.line_clear.tick(time.delta());
+        if !timers.line_clear.finished() {
+            return;
+        }
+        timers.are.tick(time.delta());
+        if !timers.are.finished() {
+            return;
+        }
+        let Some(piece_config) = piece_queue.0.pop_front() else {
+            return;
+        };
+        timers.gravity.reset();
+        last_move_is_rotation.0 = false;
+        piece_inputs.0.clear();
         // 生成新的四格骨牌
         let color = piece_config.color;
         let visibility = Visibility::Hidden;
-        let piece_type = piece_config.piece_type.clone();
+        let piece_type = piece_config.piece_type;
+        let (delta_x, delta_y) = board_size.spawn_offset();
+        let blocks = shift_piece(&piece_config.blocks, Some(delta_x), Some(delta_y));
 
-        for block in piece_config.blocks.iter() {
+        for block in blocks.iter() {
             commands
                 .spawn(piece_type)
-                .insert(new_block_sprite(&block, color, visibility))
+                .insert(new_block_sprite(block, color, visibility, &board_size))
                 .insert(*block)
+                .insert(RotationState::default())
                 .insert(Movable {
                     can_down: true,
                     can_left: true,
@@ -433,63 +677,28 @@ pub fn auto_generate_new_piece(
 }
 
 // bag7算法实现随机：每次填充7个随机排序的骨牌
-pub fn random_7_pieces() -> Vec<PieceConfig> {
-    let mut rng = rand::rng();
-    let mut piece_type_set = BTreeSet::new();
-
-    loop {
-        match rng.random_range(0..PieceType::PIECE_AMOUNT) {
-            0 => {
-                piece_type_set.insert(PieceType::I);
-            }
-            1 => {
-                piece_type_set.insert(PieceType::J);
-            }
-            2 => {
-                piece_type_set.insert(PieceType::L);
-            }
-            3 => {
-                piece_type_set.insert(PieceType::O);
-            }
-            4 => {
-                piece_type_set.insert(PieceType::S);
-            }
-            5 => {
-                piece_type_set.insert(PieceType::T);
-            }
-            6 => {
-                piece_type_set.insert(PieceType::Z);
-            }
-            _ => {
-                panic!("Random value is unexpected");
-            }
-        }
-        if piece_type_set.len() == PieceType::PIECE_AMOUNT as usize {
-            break;
-        }
-    }
-    // info!("random 7 pieces: {:?}", result);
-    piece_type_set
+pub fn random_7_pieces(rng: &mut impl Rng) -> Vec<PieceConfig> {
+    let mut piece_types = PieceType::ALL;
+    piece_types.shuffle(rng);
+    // info!("random 7 pieces: {:?}", piece_types);
+    piece_types
         .iter()
-        .map(|piece_type| {
-            PieceConfig::new(
-                *piece_type,
-                shift_piece(piece_shape(*piece_type), None, Some(20)),
-            )
-        })
+        .map(|piece_type| PieceConfig::from_type(*piece_type))
         .collect()
 }
 
-pub fn new_block_sprite(block: &Block, color: Color, visibility: Visibility) -> impl Bundle {
+pub fn new_block_sprite(
+    block: &Block,
+    color: Color,
+    visibility: Visibility,
+    board_size: &BoardSize,
+) -> impl Bundle {
+    let sticker_length = board_size.sticker_length();
     (
         Sprite { color, ..default() },
         Transform {
-            scale: Vec3::new(
-                BLOCK_STICKER_LENGTH,
-                BLOCK_STICKER_LENGTH,
-                BLOCK_STICKER_LENGTH,
-            ),
-            translation: block.translation(),
+            scale: Vec3::new(sticker_length, sticker_length, sticker_length),
+            translation: block.translation(board_size),
             ..default()
         },
         visibility,
@@ -0,0 +1,355 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+
+use crate::board::*;
+use crate::common::GameMode;
+use crate::piece::*;
+
+// 骨牌集所在的资源目录及文件后缀
+const PIECE_SET_DIR: &str = "pieces";
+const PIECE_SET_EXTENSION: &str = ".pieces.ron";
+// 无法列出资源目录时（如web版）加载的骨牌集
+const DEFAULT_PIECE_SET_PATHS: [&str; 2] = [
+    "pieces/trominoes.pieces.ron",
+    "pieces/pentominoes.pieces.ron",
+];
+
+// 可选的骨牌集，列出assets/pieces目录下的*.pieces.ron文件，按文件名排序
+#[cfg(not(target_arch = "wasm32"))]
+pub fn piece_set_paths() -> Vec<String> {
+    let dir = bevy::asset::io::file::FileAssetReader::get_base_path()
+        .join("assets")
+        .join(PIECE_SET_DIR);
+    let mut paths: Vec<String> = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|file_name| file_name.ends_with(PIECE_SET_EXTENSION))
+            .map(|file_name| format!("{}/{}", PIECE_SET_DIR, file_name))
+            .collect(),
+        Err(err) => {
+            warn!("failed to list {}: {}", dir.display(), err);
+            Vec::new()
+        }
+    };
+    if paths.is_empty() {
+        return default_piece_set_paths();
+    }
+    paths.sort();
+    paths
+}
+
+// web版无法列出资源目录，使用内置的骨牌集列表
+#[cfg(target_arch = "wasm32")]
+pub fn piece_set_paths() -> Vec<String> {
+    default_piece_set_paths()
+}
+
+fn default_piece_set_paths() -> Vec<String> {
+    DEFAULT_PIECE_SET_PATHS
+        .iter()
+        .map(|path| path.to_string())
+        .collect()
+}
+
+// 自定义骨牌
+#[derive(Debug, Clone, Deserialize)]
+pub struct PieceDefinition {
+    pub name: String,
+    // sRGB颜色
+    pub color: (f32, f32, f32),
+    // 出生时的位置，按标准10列面板定义，y为0时位于面板上方第一行
+    pub spawn: (i32, i32),
+    // 按顺时针排列的各旋转状态，每个状态下各方块相对骨牌原点的坐标，方块数需相同
+    pub rotations: Vec<Vec<(i32, i32)>>,
+    // 旋转发生碰撞时依次尝试的平移，为空时使用骨牌集的kicks
+    #[serde(default)]
+    pub kicks: Vec<(i32, i32)>,
+}
+
+impl PieceDefinition {
+    pub fn is_valid(&self) -> bool {
+        match self.rotations.first() {
+            Some(first) => {
+                !first.is_empty()
+                    && self
+                        .rotations
+                        .iter()
+                        .all(|rotation| rotation.len() == first.len())
+            }
+            None => false,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        Color::srgb(self.color.0, self.color.1, self.color.2)
+    }
+
+    // 指定旋转状态下相对骨牌原点的方块
+    pub fn cells(&self, state: usize) -> Vec<Block> {
+        self.rotations[state % self.rotations.len()]
+            .iter()
+            .map(|(x, y)| Block { x: *x, y: *y })
+            .collect()
+    }
+}
+
+// 骨牌集
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct PieceSet {
+    pub name: String,
+    #[serde(default)]
+    pub kicks: Vec<(i32, i32)>,
+    pub pieces: Vec<PieceDefinition>,
+}
+
+impl PieceSet {
+    // 去掉无效的骨牌
+    pub fn validated(&self) -> PieceSet {
+        let mut piece_set = self.clone();
+        piece_set.pieces.retain(|piece| {
+            if !piece.is_valid() {
+                warn!("invalid piece {} in piece set {}", piece.name, self.name);
+            }
+            piece.is_valid()
+        });
+        piece_set.pieces.truncate(u8::MAX as usize + 1);
+        piece_set
+    }
+
+    // 位于标准出生位置的骨牌
+    pub fn piece_config(&self, index: usize) -> PieceConfig {
+        let piece = &self.pieces[index];
+        PieceConfig {
+            piece_type: PieceType::Custom(index as u8),
+            blocks: shift_piece(&piece.cells(0), Some(piece.spawn.0), Some(piece.spawn.1)),
+            color: piece.color(),
+        }
+    }
+
+    pub fn kicks(&self, index: usize) -> &[(i32, i32)] {
+        let piece = &self.pieces[index];
+        if piece.kicks.is_empty() {
+            &self.kicks
+        } else {
+            &piece.kicks
+        }
+    }
+
+    // 按旋转状态表顺时针旋转，发生碰撞时依次尝试kicks中的平移
+    pub fn rotate(
+        &self,
+        index: usize,
+        state: usize,
+        piece: &[Block],
+        board: &[Block],
+        board_size: &BoardSize,
+    ) -> Option<(Vec<Block>, usize)> {
+        let definition = self.pieces.get(index)?;
+        let next_state = (state + 1) % definition.rotations.len();
+        // 由当前方块位置推算骨牌原点
+        let origin = definition.cells(state)[0];
+        let rotated = shift_piece(
+            &definition.cells(next_state),
+            Some(piece[0].x - origin.x),
+            Some(piece[0].y - origin.y),
+        );
+        std::iter::once(&(0, 0))
+            .chain(self.kicks(index))
+            .map(|(delta_x, delta_y)| shift_piece(&rotated, Some(*delta_x), Some(*delta_y)))
+            .find(|kicked| !is_overlapping(kicked, board, board_size))
+            .map(|kicked| (kicked, next_state))
+    }
+
+    // 与bag7相同，每次填充骨牌集中所有骨牌的随机排列
+    pub fn random_bag(&self, rng: &mut impl Rng) -> Vec<PieceConfig> {
+        let mut indexes: Vec<usize> = (0..self.pieces.len()).collect();
+        indexes.shuffle(rng);
+        indexes
+            .into_iter()
+            .map(|index| self.piece_config(index))
+            .collect()
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct PieceSetHandles(pub Vec<Handle<PieceSet>>);
+
+// 主菜单需要展示骨牌集名称，在Startup之前加载
+impl FromWorld for PieceSetHandles {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        PieceSetHandles(
+            piece_set_paths()
+                .into_iter()
+                .map(|path| asset_server.load(path))
+                .collect(),
+        )
+    }
+}
+
+// 主菜单中选择的骨牌集序号，None表示标准四格骨牌
+#[derive(Debug, Default, Resource)]
+pub struct PieceSetSetting(pub Option<usize>);
+
+// 本局使用的骨牌集，None表示标准四格骨牌
+#[derive(Debug, Default, Resource)]
+pub struct ActivePieceSet(pub Option<PieceSet>);
+
+// 按当前骨牌集生成一组随机骨牌
+pub fn random_bag(rng: &mut impl Rng, active_piece_set: &ActivePieceSet) -> Vec<PieceConfig> {
+    match &active_piece_set.0 {
+        Some(piece_set) => piece_set.random_bag(rng),
+        None => random_7_pieces(rng),
+    }
+}
+
+pub fn piece_set_label(
+    setting: &PieceSetSetting,
+    handles: &PieceSetHandles,
+    piece_sets: &Assets<PieceSet>,
+) -> String {
+    // 未加载完成时显示文件路径
+    let name = match setting.0.and_then(|index| handles.0.get(index)) {
+        None => "Standard".to_string(),
+        Some(handle) => match piece_sets.get(handle) {
+            Some(piece_set) => piece_set.name.clone(),
+            None => handle
+                .path()
+                .map_or_else(String::new, |path| path.to_string()),
+        },
+    };
+    format!("Pieces: {}", name)
+}
+
+// 依次切换标准四格骨牌和各骨牌集
+pub fn next_piece_set(setting: &PieceSetSetting, handles: &PieceSetHandles) -> PieceSetSetting {
+    PieceSetSetting(match setting.0 {
+        None if !handles.0.is_empty() => Some(0),
+        Some(index) if index + 1 < handles.0.len() => Some(index + 1),
+        _ => None,
+    })
+}
+
+// 开局时确定骨牌集并重新生成骨牌序列，谜题模式、每日挑战和定式练习固定使用标准四格骨牌
+pub fn apply_piece_set(
+    game_mode: Res<GameMode>,
+    setting: Res<PieceSetSetting>,
+    handles: Res<PieceSetHandles>,
+    piece_sets: Res<Assets<PieceSet>>,
+    mut active_piece_set: ResMut<ActivePieceSet>,
+    mut piece_rng: ResMut<PieceRng>,
+    mut piece_queue: ResMut<PieceQueue>,
+) {
+    active_piece_set.0 = match (*game_mode, setting.0) {
+        (GameMode::Puzzle | GameMode::Daily | GameMode::Opener, _) | (_, None) => None,
+        (_, Some(index)) => {
+            let handle = &handles.0[index];
+            match piece_sets.get(handle) {
+                Some(piece_set) => Some(piece_set.validated()),
+                None => {
+                    warn!("piece set {:?} is not loaded yet", handle.path());
+                    None
+                }
+            }
+        }
+    };
+    if let Some(piece_set) = &active_piece_set.0
+        && piece_set.pieces.is_empty()
+    {
+        warn!("piece set {} has no valid pieces", piece_set.name);
+        active_piece_set.0 = None;
+    }
+    info!(
+        "piece set: {}",
+        active_piece_set
+            .0
+            .as_ref()
+            .map_or("Standard", |piece_set| piece_set.name.as_str())
+    );
+    piece_queue.0 = VecDeque::from(random_bag(&mut piece_rng.0, &active_piece_set));
+}
+
+// 顺时针旋转骨牌，返回旋转后的方块和旋转状态，无法旋转时返回None
+pub fn rotate_blocks(
+    piece_type: PieceType,
+    state: usize,
+    piece: &[Block],
+    board: &[Block],
+    board_size: &BoardSize,
+    active_piece_set: &ActivePieceSet,
+) -> Option<(Vec<Block>, usize)> {
+    match piece_type {
+        PieceType::Custom(index) => {
+            active_piece_set
+                .0
+                .as_ref()?
+                .rotate(index as usize, state, piece, board, board_size)
+        }
+        _ => rotate_standard(piece_type, state, piece, board, board_size)
+            .map(|rotated| (rotated, (state + 1) % 4)),
+    }
+}
+
+// 骨牌在面板上的出生位置
+pub fn spawn_blocks(
+    piece_type: PieceType,
+    active_piece_set: &ActivePieceSet,
+    board_size: &BoardSize,
+) -> Option<Vec<Block>> {
+    let piece_config = match piece_type {
+        PieceType::Custom(index) => active_piece_set
+            .0
+            .as_ref()
+            .filter(|piece_set| (index as usize) < piece_set.pieces.len())?
+            .piece_config(index as usize),
+        _ => PieceConfig::from_type(piece_type),
+    };
+    let (delta_x, delta_y) = board_size.spawn_offset();
+    Some(shift_piece(
+        &piece_config.blocks,
+        Some(delta_x),
+        Some(delta_y),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_piece_sets_are_valid() {
+        for content in [
+            include_str!("../assets/pieces/trominoes.pieces.ron"),
+            include_str!("../assets/pieces/pentominoes.pieces.ron"),
+        ] {
+            let piece_set: PieceSet = ron::from_str(content).expect("valid piece set");
+            assert!(
+                !piece_set.pieces.is_empty(),
+                "{} has no pieces",
+                piece_set.name
+            );
+            for piece in &piece_set.pieces {
+                assert!(
+                    piece.is_valid(),
+                    "{} in {} is invalid",
+                    piece.name,
+                    piece_set.name
+                );
+            }
+        }
+    }
+
+    // 内置列表与资源目录中的骨牌集保持一致
+    #[test]
+    fn lists_piece_set_directory() {
+        assert_eq!(piece_set_paths(), {
+            let mut paths = default_piece_set_paths();
+            paths.sort();
+            paths
+        });
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::board::*;
+use crate::common::*;
+use crate::piece::*;
+
+// 谜题目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PuzzleGoal {
+    // 消除指定行数
+    ClearLines(u32),
+    // 全清
+    PerfectClear,
+    // T-spin消三行
+    TSpinTriple,
+}
+
+impl PuzzleGoal {
+    pub fn description(&self) -> String {
+        match self {
+            PuzzleGoal::ClearLines(lines) => format!("Clear {} lines", lines),
+            PuzzleGoal::PerfectClear => "Perfect clear".to_string(),
+            PuzzleGoal::TSpinTriple => "T-spin triple".to_string(),
+        }
+    }
+
+    // 本次消行后是否达成目标，lines为累计消除的行数
+    pub fn is_passed(&self, lines: u32, event: &LinesCleared) -> bool {
+        match self {
+            PuzzleGoal::ClearLines(goal_lines) => lines >= *goal_lines,
+            PuzzleGoal::PerfectClear => event.perfect_clear,
+            PuzzleGoal::TSpinTriple => event.t_spin && event.count == 3,
+        }
+    }
+}
+
+// 谜题
+#[derive(Debug, Clone, Deserialize)]
+pub struct Puzzle {
+    pub name: String,
+    // 初始面板，自上而下每行一个字符串，'.'表示空格，
+    // 骨牌字母（IJLOSTZ）表示对应颜色的方块，其他字符表示垃圾方块
+    pub board: Vec<String>,
+    // 固定的骨牌序列
+    pub pieces: Vec<PieceType>,
+    pub goal: PuzzleGoal,
+}
+
+impl Puzzle {
+    // 初始面板方块及颜色，超出面板宽度的部分被忽略
+    pub fn blocks(&self, board_size: &BoardSize) -> Vec<(Block, Color)> {
+        let mut blocks = Vec::new();
+        for (row, line) in self.board.iter().enumerate() {
+            let y = (self.board.len() - 1 - row) as i32;
+            if line.chars().count() != board_size.cols as usize {
+                warn!("puzzle {} row {} width mismatch: {}", self.name, row, line);
+            }
+            for (x, c) in line.chars().take(board_size.cols as usize).enumerate() {
+                let color = match c {
+                    '.' | ' ' => continue,
+                    'I' => piece_color(PieceType::I),
+                    'J' => piece_color(PieceType::J),
+                    'L' => piece_color(PieceType::L),
+                    'O' => piece_color(PieceType::O),
+                    'S' => piece_color(PieceType::S),
+                    'T' => piece_color(PieceType::T),
+                    'Z' => piece_color(PieceType::Z),
+                    _ => GARBAGE_COLOR,
+                };
+                blocks.push((Block { x: x as i32, y }, color));
+            }
+        }
+        blocks
+    }
+}
+
+// 谜题集，从assets/puzzles/*.puzzles.ron加载
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct PuzzleSet {
+    pub puzzles: Vec<Puzzle>,
+}
+
+impl PuzzleSet {
+    // 序号超出时循环
+    pub fn puzzle(&self, index: usize) -> Option<&Puzzle> {
+        if self.puzzles.is_empty() {
+            None
+        } else {
+            Some(&self.puzzles[index % self.puzzles.len()])
+        }
+    }
+}
+
+#[derive(Debug, Resource)]
+pub struct PuzzleSetHandle(pub Handle<PuzzleSet>);
+
+// 当前谜题序号
+#[derive(Debug, Default, Resource)]
+pub struct CurrentPuzzle(pub usize);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PuzzleStatus {
+    #[default]
+    Playing,
+    Passed,
+    Failed,
+}
+
+// 当前谜题进度
+#[derive(Debug, Default, Resource)]
+pub struct PuzzleProgress {
+    pub lines: u32,
+    pub status: PuzzleStatus,
+}
+
+// 谜题说明及结果
+#[derive(Debug, Component)]
+pub struct PuzzleInfoboard;
+
+pub fn setup_puzzle_set(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(PuzzleSetHandle(
+        asset_server.load("puzzles/default.puzzles.ron"),
+    ));
+}
+
+pub fn setup_puzzle_infoboard(mut commands: Commands) {
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Visibility::Hidden,
+        PuzzleInfoboard,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
+
+// 开局时注入初始面板和骨牌序列
+pub fn setup_puzzle(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
+    puzzle_set_handle: Res<PuzzleSetHandle>,
+    puzzle_sets: Res<Assets<PuzzleSet>>,
+    current_puzzle: Res<CurrentPuzzle>,
+    mut progress: ResMut<PuzzleProgress>,
+    mut piece_queue: ResMut<PieceQueue>,
+) {
+    if *game_mode != GameMode::Puzzle {
+        return;
+    }
+    let Some(puzzle_set) = puzzle_sets.get(&puzzle_set_handle.0) else {
+        warn!("puzzle set is not loaded yet");
+        return;
+    };
+    let Some(puzzle) = puzzle_set.puzzle(current_puzzle.0) else {
+        warn!("puzzle set is empty");
+        return;
+    };
+    info!("setup puzzle: {}", puzzle.name);
+    for (block, color) in puzzle.blocks(&board_size) {
+        spawn_board_block(&mut commands, block, color, &board_size);
+    }
+    piece_queue.0 = puzzle
+        .pieces
+        .iter()
+        .map(|piece_type| PieceConfig::from_type(*piece_type))
+        .collect::<VecDeque<_>>();
+    *progress = PuzzleProgress::default();
+}
+
+// 检查是否达成目标，骨牌用完仍未达成则失败
+pub fn check_puzzle_goal(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    puzzle_set_handle: Res<PuzzleSetHandle>,
+    puzzle_sets: Res<Assets<PuzzleSet>>,
+    current_puzzle: Res<CurrentPuzzle>,
+    mut progress: ResMut<PuzzleProgress>,
+    mut piece_queue: ResMut<PieceQueue>,
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    q_piece: Query<(), With<PieceType>>,
+) {
+    let Some(puzzle) = puzzle_sets
+        .get(&puzzle_set_handle.0)
+        .and_then(|puzzle_set| puzzle_set.puzzle(current_puzzle.0))
+    else {
+        return;
+    };
+    if progress.status != PuzzleStatus::Playing {
+        lines_cleared_events.clear();
+        return;
+    }
+    for event in lines_cleared_events.read() {
+        progress.lines += event.count;
+        if puzzle.goal.is_passed(progress.lines, event) {
+            progress.status = PuzzleStatus::Passed;
+        }
+    }
+    if progress.status == PuzzleStatus::Playing && piece_queue.0.is_empty() && q_piece.is_empty() {
+        progress.status = PuzzleStatus::Failed;
+    }
+
+    match progress.status {
+        PuzzleStatus::Playing => {}
+        PuzzleStatus::Passed => {
+            info!("puzzle passed: {}", puzzle.name);
+            commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+            piece_queue.0.clear();
+        }
+        PuzzleStatus::Failed => {
+            info!("puzzle failed: {}", puzzle.name);
+            commands.spawn(AudioPlayer(game_audios.gameover.clone()));
+        }
+    }
+}
+
+// R键立即重试，通过后按回车键进入下一题
+pub fn handle_puzzle_input(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    progress: Res<PuzzleProgress>,
+    mut current_puzzle: ResMut<CurrentPuzzle>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::KeyR) {
+        game_state.set(GameState::GameRestarted);
+    } else if keyboard_input.just_pressed(KeyCode::Enter) && progress.status == PuzzleStatus::Passed
+    {
+        current_puzzle.0 += 1;
+        game_state.set(GameState::GameRestarted);
+    }
+}
+
+pub fn update_puzzle_infoboard(
+    game_mode: Res<GameMode>,
+    puzzle_set_handle: Res<PuzzleSetHandle>,
+    puzzle_sets: Res<Assets<PuzzleSet>>,
+    current_puzzle: Res<CurrentPuzzle>,
+    progress: Res<PuzzleProgress>,
+    q_infoboard: Single<(&mut Visibility, &Children), With<PuzzleInfoboard>>,
+    mut q_text: Query<&mut Text>,
+) {
+    let (mut visibility, children) = q_infoboard.into_inner();
+    let puzzle_set = puzzle_sets.get(&puzzle_set_handle.0);
+    let (Some(puzzle_set), GameMode::Puzzle) = (puzzle_set, *game_mode) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    let Some(puzzle) = puzzle_set.puzzle(current_puzzle.0) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Visible;
+
+    let mut info = format!(
+        "Puzzle {}/{} {}: {}",
+        current_puzzle.0 % puzzle_set.puzzles.len() + 1,
+        puzzle_set.puzzles.len(),
+        puzzle.name,
+        puzzle.goal.description()
+    );
+    match progress.status {
+        PuzzleStatus::Playing => info.push_str("\nR: retry"),
+        PuzzleStatus::Passed => info.push_str("\nPassed! Enter: next puzzle, R: retry"),
+        PuzzleStatus::Failed => info.push_str("\nFailed! R: retry"),
+    }
+    for child in children {
+        if let Ok(mut text) = q_text.get_mut(*child)
+            && text.0 != info
+        {
+            text.0 = info.clone();
+        }
+    }
+}
+
+pub fn reset_current_puzzle(mut current_puzzle: ResMut<CurrentPuzzle>) {
+    current_puzzle.0 = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{placements, simulate};
+    use crate::engine::Game;
+
+    // 按固定的骨牌序列搜索所有落点，能否在骨牌用完前达成目标
+    fn is_solvable(game: &Game, pieces: &[PieceType], lines: u32, goal: PuzzleGoal) -> bool {
+        let Some((piece_type, rest)) = pieces.split_first() else {
+            return false;
+        };
+        let mut game = game.clone();
+        game.queue = VecDeque::from([*piece_type]);
+        if !game.spawn_piece() {
+            return false;
+        }
+        placements(&game).iter().any(|placement| {
+            let (after, lines_cleared) = simulate(&game, placement);
+            match lines_cleared {
+                Some(event) if goal.is_passed(lines + event.count, &event) => true,
+                Some(event) => is_solvable(&after, rest, lines + event.count, goal),
+                None => is_solvable(&after, rest, lines, goal),
+            }
+        })
+    }
+
+    #[test]
+    fn puzzles_are_solvable() {
+        let puzzle_set: PuzzleSet =
+            ron::from_str(include_str!("../assets/puzzles/default.puzzles.ron"))
+                .expect("valid puzzle set");
+        let board_size = BoardSize::default();
+        for puzzle in &puzzle_set.puzzles {
+            let mut game = Game::new(board_size, 0);
+            game.board = puzzle
+                .blocks(&board_size)
+                .into_iter()
+                .map(|(block, color)| (color, block))
+                .collect();
+            assert!(
+                is_solvable(&game, &puzzle.pieces, 0, puzzle.goal),
+                "{} is not solvable",
+                puzzle.name
+            );
+        }
+    }
+}
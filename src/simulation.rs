@@ -0,0 +1,260 @@
+use std::time::Duration;
+
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+
+use crate::attack::AttackTable;
+use crate::board::{BoardSize, LinesCleared};
+use crate::bot::*;
+use crate::cpu::CpuDifficulty;
+use crate::engine::Game;
+
+// 不依赖ECS和窗口的机器人对局模拟，用于比赛和调整权重
+// 骨牌按PPS直接放到落点，不模拟重力和锁定延迟
+
+// 模拟的时间步长，与游戏的60帧一致
+const SIMULATION_STEP: Duration = Duration::from_micros(16_667);
+// 竞速模式的目标行数
+pub const SPRINT_LINES: u32 = 40;
+
+// 参加模拟的机器人
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub name: String,
+    // 每秒放置的骨牌数
+    pub pps: f32,
+    // 搜索时考虑的骨牌数（包括当前骨牌）
+    pub depth: usize,
+    // 随机选择落点的概率
+    pub mistake_rate: f64,
+    pub weights: BotWeights,
+}
+
+impl BotConfig {
+    pub fn from_difficulty(difficulty: &CpuDifficulty) -> Self {
+        BotConfig {
+            name: difficulty.name.to_string(),
+            pps: difficulty.pps,
+            depth: difficulty.depth,
+            mistake_rate: difficulty.mistake_rate,
+            weights: BotWeights::default(),
+        }
+    }
+}
+
+// 单人模拟的目标
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoloGoal {
+    // 尽快消除40行
+    Sprint,
+    // 放置到骨牌数上限前不堆到顶部
+    Marathon,
+}
+
+// 一个面板的结果
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct GameResult {
+    // 对战中对方先堆到顶部，或单人模式达到目标
+    pub won: bool,
+    pub topped_out: bool,
+    pub pieces: u32,
+    pub lines: u32,
+    pub attack: u32,
+    pub seconds: f32,
+}
+
+impl GameResult {
+    pub fn pps(&self) -> f32 {
+        if self.seconds > 0.0 {
+            self.pieces as f32 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn apm(&self) -> f32 {
+        if self.seconds > 0.0 {
+            self.attack as f32 * 60.0 / self.seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+// 模拟中的一个玩家
+struct SimulatedPlayer<'a> {
+    config: &'a BotConfig,
+    game: Game,
+    // 随机失误使用单独的随机数，保证结果可以复现
+    rng: ChaCha8Rng,
+    // 距离放置下一个骨牌的时间
+    cooldown: Duration,
+}
+
+impl<'a> SimulatedPlayer<'a> {
+    fn new(config: &'a BotConfig, seed: u64, index: u64) -> Self {
+        SimulatedPlayer {
+            config,
+            game: Game::new(BoardSize::default(), seed),
+            rng: ChaCha8Rng::seed_from_u64(seed.wrapping_mul(31).wrapping_add(index)),
+            cooldown: Duration::ZERO,
+        }
+    }
+
+    // 到时间时取出骨牌并放到落点，返回锁定时的消行
+    fn step(&mut self, delta: Duration) -> Option<LinesCleared> {
+        self.game.tick_garbage(delta);
+        self.cooldown = self.cooldown.saturating_sub(delta);
+        if !self.cooldown.is_zero() || self.game.topped_out {
+            return None;
+        }
+        self.cooldown = Duration::from_secs_f32(1.0 / self.config.pps);
+        if self.game.piece.is_none() && !self.game.spawn_piece() {
+            return None;
+        }
+        let placement = if self.rng.random_bool(self.config.mistake_rate) {
+            placements(&self.game).choose(&mut self.rng).cloned()
+        } else {
+            best_placement(&self.game, self.config.depth, &self.config.weights)
+        };
+        let Some(placement) = placement else {
+            return self.game.hard_drop();
+        };
+        let (after, lines_cleared) = simulate(&self.game, &placement);
+        self.game = after;
+        lines_cleared
+    }
+
+    fn result(&self, won: bool, elapsed: Duration) -> GameResult {
+        GameResult {
+            won,
+            topped_out: self.game.topped_out,
+            pieces: self.game.stats.pieces,
+            lines: self.game.stats.lines,
+            attack: self.game.stats.attack,
+            seconds: elapsed.as_secs_f32(),
+        }
+    }
+}
+
+// 单人模拟，超过骨牌数上限时结束
+pub fn run_solo(
+    config: &BotConfig,
+    goal: SoloGoal,
+    seed: u64,
+    max_pieces: u32,
+    table: &AttackTable,
+) -> GameResult {
+    let mut player = SimulatedPlayer::new(config, seed, 0);
+    let mut elapsed = Duration::ZERO;
+    loop {
+        elapsed += SIMULATION_STEP;
+        if let Some(lines_cleared) = player.step(SIMULATION_STEP) {
+            player.game.resolve_attack(&lines_cleared, table);
+        }
+        let stats = &player.game.stats;
+        if player.game.topped_out {
+            return player.result(false, elapsed);
+        }
+        if goal == SoloGoal::Sprint && stats.lines >= SPRINT_LINES {
+            return player.result(true, elapsed);
+        }
+        if stats.pieces >= max_pieces {
+            return player.result(goal == SoloGoal::Marathon, elapsed);
+        }
+    }
+}
+
+// 双人对战模拟，两个面板使用相同的骨牌序列，超过时间上限时为平局
+pub fn run_versus(
+    configs: [&BotConfig; 2],
+    seed: u64,
+    max_time: Duration,
+    table: &AttackTable,
+) -> [GameResult; 2] {
+    let mut players = [0, 1].map(|index| SimulatedPlayer::new(configs[index], seed, index as u64));
+    let mut elapsed = Duration::ZERO;
+    while elapsed < max_time && !players.iter().any(|player| player.game.topped_out) {
+        elapsed += SIMULATION_STEP;
+        for index in 0..players.len() {
+            let Some(lines_cleared) = players[index].step(SIMULATION_STEP) else {
+                continue;
+            };
+            let attack = players[index].game.resolve_attack(&lines_cleared, table);
+            players[1 - index]
+                .game
+                .receive_garbage(attack, table.garbage_delay());
+        }
+    }
+    [0, 1].map(|index| {
+        let won = !players[index].game.topped_out && players[1 - index].game.topped_out;
+        players[index].result(won, elapsed)
+    })
+}
+
+// 读取assets/bots/*.weights.ron格式的权重文件
+pub fn load_weights(path: &str) -> Result<BotWeights, String> {
+    let content = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    ron::from_str(&content).map_err(|err| format!("{}: {}", path, err))
+}
+
+// 保存权重文件，comment写在文件开头
+pub fn save_weights(path: &str, weights: &BotWeights, comment: &str) -> Result<(), String> {
+    let content = ron::ser::to_string_pretty(weights, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())?;
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent).map_err(|err| format!("{}: {}", path, err))?;
+    }
+    std::fs::write(path, format!("// {}\n{}\n", comment, content))
+        .map_err(|err| format!("{}: {}", path, err))
+}
+
+// 在多个线程中分别模拟，结果保持输入的顺序
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("simulation thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPU_DIFFICULTY_PRESETS;
+
+    // 失误率较高的机器人，同时覆盖随机失误的分支
+    fn config() -> BotConfig {
+        BotConfig::from_difficulty(&CPU_DIFFICULTY_PRESETS[0])
+    }
+
+    #[test]
+    fn solo_is_deterministic_for_seed() {
+        let table = AttackTable::default();
+        let first = run_solo(&config(), SoloGoal::Marathon, 7, 40, &table);
+        let second = run_solo(&config(), SoloGoal::Marathon, 7, 40, &table);
+        assert_eq!(first, second);
+        assert_eq!(first.pieces, 40);
+    }
+
+    #[test]
+    fn versus_is_deterministic_for_seed() {
+        let table = AttackTable::default();
+        let (first, second) = (config(), config());
+        let max_time = Duration::from_secs(30);
+        assert_eq!(
+            run_versus([&first, &second], 7, max_time, &table),
+            run_versus([&first, &second], 7, max_time, &table)
+        );
+    }
+}
@@ -1,15 +1,13 @@
 use bevy::prelude::*;
 
 use crate::{
-    board::{BLOCK_LENGTH, Block},
-    new_block_sprite,
-    piece::{PieceQueue, PieceType},
-    piece_shape, shift_piece,
+    board::{Block, BoardSize, LinesCleared},
+    piece::{PieceQueue, PieceType, new_block_sprite, shift_piece},
 };
 
 // 计分板长宽
-const STATS_BOARD_LENGTH: f32 = 280.0;
-const STATS_BOARD_WIDTH: f32 = 50.0;
+pub const STATS_BOARD_LENGTH: f32 = 280.0;
+pub const STATS_BOARD_WIDTH: f32 = 50.0;
 
 // 分数
 #[derive(Resource)]
@@ -30,14 +28,21 @@ pub struct NextPieceType(pub Option<PieceType>);
 #[derive(Debug, Component)]
 pub struct NextPieceBoard;
 
-pub fn setup_stats_boards(mut commands: Commands, q_window: Single<&Window>) {
-    // 通过窗口大小和棋盘大小计算stats位置
-    // gameboard左上角在窗口上的位置
-    let gameboard_left_corner_pos = (
-        q_window.physical_width() as f32 / 2.0 - 5.0 * BLOCK_LENGTH,
-        q_window.physical_height() as f32 / 2.0 - 10.0 * BLOCK_LENGTH,
-    );
-    info!("gameboard_left_corner_pos: {:?}", gameboard_left_corner_pos);
+// 位于gameboard左侧的统计板，自上而下第几行
+#[derive(Debug, Component)]
+pub struct StatsBoardRow(pub u32);
+
+// 通过窗口大小和棋盘大小计算stats位置
+// gameboard左上角在窗口上的位置
+pub fn gameboard_left_corner_pos(window: &Window, board_size: &BoardSize) -> (f32, f32) {
+    (
+        window.physical_width() as f32 / 2.0 - board_size.width() / 2.0,
+        window.physical_height() as f32 / 2.0 - board_size.height() / 2.0,
+    )
+}
+
+pub fn setup_stats_boards(mut commands: Commands) {
+    // 位置由layout_stats_boards根据面板大小设置
     // 分数
     commands
         .spawn((
@@ -49,10 +54,9 @@ pub fn setup_stats_boards(mut commands: Commands, q_window: Single<&Window>) {
             TextColor(Color::srgb(0.5, 0.5, 1.0)),
             Node {
                 position_type: PositionType::Absolute,
-                top: Val::Px(gameboard_left_corner_pos.1),
-                left: Val::Px(gameboard_left_corner_pos.0 - STATS_BOARD_LENGTH),
                 ..default()
             },
+            StatsBoardRow(0),
         ))
         .with_child((
             TextSpan::new("0"),
@@ -75,10 +79,9 @@ pub fn setup_stats_boards(mut commands: Commands, q_window: Single<&Window>) {
             TextColor(Color::srgb(0.5, 0.5, 1.0)),
             Node {
                 position_type: PositionType::Absolute,
-                top: Val::Px(gameboard_left_corner_pos.1 + STATS_BOARD_WIDTH),
-                left: Val::Px(gameboard_left_corner_pos.0 - STATS_BOARD_LENGTH),
                 ..default()
             },
+            StatsBoardRow(1),
         ))
         .with_child((
             TextSpan::new("0"),
@@ -91,6 +94,20 @@ pub fn setup_stats_boards(mut commands: Commands, q_window: Single<&Window>) {
         ));
 }
 
+// 面板大小改变时，统计板跟随gameboard左上角
+pub fn layout_stats_boards(
+    q_window: Single<&Window>,
+    board_size: Res<BoardSize>,
+    mut q_stats: Query<(&mut Node, &StatsBoardRow)>,
+) {
+    let gameboard_left_corner_pos = gameboard_left_corner_pos(&q_window, &board_size);
+    info!("gameboard_left_corner_pos: {:?}", gameboard_left_corner_pos);
+    for (mut node, row) in &mut q_stats {
+        node.top = Val::Px(gameboard_left_corner_pos.1 + row.0 as f32 * STATS_BOARD_WIDTH);
+        node.left = Val::Px(gameboard_left_corner_pos.0 - STATS_BOARD_LENGTH);
+    }
+}
+
 pub fn update_scoreboard(score: Res<Score>, q_span: Single<&mut TextSpan, With<Scoreboard>>) {
     **q_span.into_inner() = score.0.to_string();
 }
@@ -99,6 +116,28 @@ pub fn update_linesboard(lines: Res<Lines>, q_span: Single<&mut TextSpan, With<L
     **q_span.into_inner() = lines.0.to_string();
 }
 
+// 根据消除行数增加分数，连锁消行按连锁数加倍
+pub fn score_cleared_lines(
+    mut lines_cleared_events: EventReader<LinesCleared>,
+    mut score: ResMut<Score>,
+) {
+    for event in lines_cleared_events.read() {
+        score.0 += line_score(event.count) * (event.chain + 1);
+    }
+}
+
+// 一次消除count行的得分
+pub fn line_score(count: u32) -> u32 {
+    match count {
+        0 => 0,
+        1 => 100,
+        2 => 200,
+        3 => 400,
+        4 => 800,
+        _ => 1000,
+    }
+}
+
 pub fn reset_score(mut score: ResMut<Score>) {
     score.0 = 0;
 }
@@ -110,38 +149,46 @@ pub fn reset_lines(mut lines: ResMut<Lines>) {
 pub fn update_next_piece_board(
     mut commands: Commands,
     piece_queue: Res<PieceQueue>,
+    board_size: Res<BoardSize>,
     mut next_piece_type: ResMut<NextPieceType>,
     query: Query<Entity, With<NextPieceBoard>>,
 ) {
+    let front_piece_type = piece_queue.0.front().map(|config| config.piece_type);
     if next_piece_type.0.is_none()
-        || piece_queue.0.front().unwrap().piece_type != next_piece_type.0.unwrap()
+        || front_piece_type != next_piece_type.0
+        || board_size.is_changed()
     {
-        next_piece_type.0 = Some(piece_queue.0.front().unwrap().piece_type);
+        next_piece_type.0 = front_piece_type;
         // 销毁原board
         for entity in &query {
             commands.entity(entity).despawn();
         }
-        let piece_type = piece_queue.0.front().unwrap().piece_type;
-        let color = piece_queue.0.front().unwrap().color;
-        let blocks = shift_piece(piece_shape(piece_type), Some(8), Some(17));
-        spawn_next_piece_board(&mut commands, blocks, color);
+        // 骨牌序列用完时不再展示
+        let Some(piece_config) = piece_queue.0.front() else {
+            return;
+        };
+        // 展示在面板右上角外侧
+        let blocks = shift_piece(
+            &piece_config.blocks,
+            Some(board_size.cols - 2),
+            Some(board_size.rows - 3),
+        );
+        spawn_next_piece_board(&mut commands, &blocks, piece_config.color, &board_size);
     }
 }
 
-pub fn spawn_next_piece_board(commands: &mut Commands, blocks: [Block; 4], color: Color) {
+pub fn spawn_next_piece_board(
+    commands: &mut Commands,
+    blocks: &[Block],
+    color: Color,
+    board_size: &BoardSize,
+) {
     let visibility = Visibility::Visible;
-    commands
-        .spawn(new_block_sprite(&blocks[0], color, visibility))
-        .insert(NextPieceBoard);
-    commands
-        .spawn(new_block_sprite(&blocks[1], color, visibility))
-        .insert(NextPieceBoard);
-    commands
-        .spawn(new_block_sprite(&blocks[2], color, visibility))
-        .insert(NextPieceBoard);
-    commands
-        .spawn(new_block_sprite(&blocks[3], color, visibility))
-        .insert(NextPieceBoard);
+    for block in blocks {
+        commands
+            .spawn(new_block_sprite(block, color, visibility, board_size))
+            .insert(NextPieceBoard);
+    }
 }
 
 pub fn clear_next_piece_board(mut commands: Commands, query: Query<Entity, With<NextPieceBoard>>) {
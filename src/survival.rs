@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::board::*;
+use crate::common::GameMode;
+use crate::piece::PieceType;
+
+// 垃圾行初始上升间隔
+pub const GARBAGE_RISE_INITIAL_INTERVAL: Duration = Duration::from_secs(10);
+// 垃圾行最短上升间隔
+pub const GARBAGE_RISE_MIN_INTERVAL: Duration = Duration::from_millis(1500);
+// 每升起一行后，上升间隔缩短为原来的比例
+pub const GARBAGE_RISE_INTERVAL_FACTOR: f32 = 0.93;
+
+// 警示条宽度及与边界的间距
+pub const GARBAGE_METER_WIDTH: f32 = 10.0;
+pub const GARBAGE_METER_GAP: f32 = 5.0;
+
+// 垃圾行上升计时器
+#[derive(Debug, Resource)]
+pub struct GarbageRiseTimer(pub Timer);
+
+impl Default for GarbageRiseTimer {
+    fn default() -> Self {
+        GarbageRiseTimer(Timer::new(
+            GARBAGE_RISE_INITIAL_INTERVAL,
+            TimerMode::Repeating,
+        ))
+    }
+}
+
+// 垃圾行上升警示条
+#[derive(Debug, Component)]
+pub struct GarbageRiseMeter;
+
+pub fn setup_garbage_rise_meter(mut commands: Commands) {
+    // 位于左侧边界外，自下而上增长，位置由update_garbage_rise_meter根据面板大小设置
+    commands.spawn((
+        Transform {
+            scale: Vec3 {
+                x: GARBAGE_METER_WIDTH,
+                y: 0.0,
+                z: 0.0,
+            },
+            ..default()
+        },
+        Sprite::default(),
+        Visibility::Hidden,
+        GarbageRiseMeter,
+    ));
+}
+
+// 定时从底部升起垃圾行，并逐渐缩短间隔
+pub fn rise_garbage(
+    mut commands: Commands,
+    mut timer: ResMut<GarbageRiseTimer>,
+    board_size: Res<BoardSize>,
+    time: Res<Time>,
+    mut q_board: Query<(&mut Block, &mut Transform), Without<PieceType>>,
+    mut q_piece: Query<(&mut Block, &mut Transform), With<PieceType>>,
+) {
+    timer.0.tick(time.delta());
+    if timer.0.just_finished() {
+        let hole = rand::rng().random_range(0..board_size.cols);
+        insert_garbage_rows(
+            &mut commands,
+            &[hole],
+            &board_size,
+            &mut q_board,
+            &mut q_piece,
+        );
+
+        let interval = timer
+            .0
+            .duration()
+            .mul_f32(GARBAGE_RISE_INTERVAL_FACTOR)
+            .max(GARBAGE_RISE_MIN_INTERVAL);
+        timer.0.set_duration(interval);
+        info!("garbage rose, next interval: {:?}", interval);
+    }
+}
+
+// 警示条高度表示距离下一次升起的进度，颜色由黄变红
+pub fn update_garbage_rise_meter(
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
+    timer: Res<GarbageRiseTimer>,
+    q_meter: Single<(&mut Transform, &mut Sprite, &mut Visibility), With<GarbageRiseMeter>>,
+) {
+    let (mut transform, mut sprite, mut visibility) = q_meter.into_inner();
+    if *game_mode != GameMode::Survival {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Visible;
+
+    let fraction = timer.0.fraction();
+    let height = fraction * board_size.height();
+    transform.scale.y = height;
+    transform.translation.x = -board_size.width() / 2.0
+        - BORDER_THICKNESS
+        - GARBAGE_METER_GAP
+        - GARBAGE_METER_WIDTH / 2.0;
+    transform.translation.y = -board_size.height() / 2.0 + height / 2.0;
+    sprite.color = Color::srgb(1.0, 1.0 - fraction, 0.0);
+}
+
+pub fn reset_garbage_rise_timer(mut timer: ResMut<GarbageRiseTimer>) {
+    *timer = GarbageRiseTimer::default();
+}
@@ -0,0 +1,603 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, channel};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::board::*;
+use crate::bot::*;
+use crate::engine::Game;
+use crate::piece::*;
+
+// Tetris Bot Protocol：通过标准输入输出与外部机器人进程交换每行一条的JSON消息
+// 面板固定为10列40行，y轴从下往上
+
+// TBP面板的行数
+pub const TBP_BOARD_ROWS: usize = 40;
+// 告知机器人的骨牌数（包括当前骨牌）
+const TBP_QUEUE_LEN: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Spin {
+    None,
+    Mini,
+    Full,
+}
+
+// 骨牌的位置，x和y为骨牌中心方块的坐标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceLocation {
+    #[serde(rename = "type")]
+    pub piece_type: PieceType,
+    pub orientation: Orientation,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub location: PieceLocation,
+    pub spin: Spin,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Start {
+    pub hold: Option<PieceType>,
+    // 第一个为当前骨牌
+    pub queue: Vec<PieceType>,
+    pub combo: u32,
+    pub back_to_back: bool,
+    // 从下往上的每一行，空格为null，其余为骨牌类型或垃圾行"G"
+    pub board: Vec<Vec<Option<char>>>,
+}
+
+// 游戏发送给机器人的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    Rules,
+    Start(Start),
+    Stop,
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: PieceType,
+    },
+    Quit,
+}
+
+// 机器人发送给游戏的消息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    Ready,
+    Error {
+        reason: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+}
+
+// 骨牌朝北时各方块相对中心的位置
+fn north_cells(piece_type: PieceType) -> Option<[(i32, i32); 4]> {
+    Some(match piece_type {
+        PieceType::I => [(-1, 0), (0, 0), (1, 0), (2, 0)],
+        PieceType::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        PieceType::T => [(-1, 0), (0, 0), (1, 0), (0, 1)],
+        PieceType::L => [(-1, 0), (0, 0), (1, 0), (1, 1)],
+        PieceType::J => [(-1, 0), (0, 0), (1, 0), (-1, 1)],
+        PieceType::S => [(-1, 0), (0, 0), (0, 1), (1, 1)],
+        PieceType::Z => [(-1, 1), (0, 1), (0, 0), (1, 0)],
+        PieceType::Custom(_) => return None,
+    })
+}
+
+const ORIENTATIONS: [Orientation; 4] = [
+    Orientation::North,
+    Orientation::East,
+    Orientation::South,
+    Orientation::West,
+];
+
+// 按朝向顺时针旋转后各方块相对中心的位置，按(y, x)排序
+fn relative_cells(piece_type: PieceType, orientation: Orientation) -> Option<Vec<Block>> {
+    let turns = ORIENTATIONS.iter().position(|o| *o == orientation)?;
+    let mut cells: Vec<Block> = north_cells(piece_type)?
+        .iter()
+        .map(|(x, y)| (0..turns).fold(Block { x: *x, y: *y }, |b, _| Block { x: b.y, y: -b.x }))
+        .collect();
+    cells.sort_by_key(|block| (block.y, block.x));
+    Some(cells)
+}
+
+pub fn location_blocks(location: &PieceLocation) -> Option<Vec<Block>> {
+    let cells = relative_cells(location.piece_type, location.orientation)?;
+    Some(shift_piece(&cells, Some(location.x), Some(location.y)))
+}
+
+// 落点对应的TBP操作，方块与朝向的形状不符时返回None
+pub fn placement_move(piece_type: PieceType, placement: &Placement) -> Option<Move> {
+    let orientation = ORIENTATIONS[placement.state % ORIENTATIONS.len()];
+    let cells = relative_cells(piece_type, orientation)?;
+    let blocks = &placement.blocks;
+    let x = blocks.iter().map(|block| block.x).min()? - cells.iter().map(|block| block.x).min()?;
+    let y = blocks.iter().map(|block| block.y).min()? - cells.iter().map(|block| block.y).min()?;
+    let location = PieceLocation {
+        piece_type,
+        orientation,
+        x,
+        y,
+    };
+    (location_blocks(&location)? == *blocks).then_some(Move {
+        location,
+        spin: if placement.t_spin {
+            Spin::Full
+        } else {
+            Spin::None
+        },
+    })
+}
+
+// 在当前骨牌可以到达的落点中找到与操作相同的一个，优先选择T-spin相同的
+pub fn find_placement(game: &Game, mv: &Move) -> Option<Placement> {
+    let piece = game.piece.as_ref()?;
+    if piece.piece_type != mv.location.piece_type {
+        return None;
+    }
+    let blocks = location_blocks(&mv.location)?;
+    let t_spin = mv.spin != Spin::None;
+    let mut matched: Vec<Placement> = placements(game)
+        .into_iter()
+        .filter(|placement| placement.blocks == blocks)
+        .collect();
+    matched.sort_by_key(|placement| placement.t_spin != t_spin);
+    matched.into_iter().next()
+}
+
+fn piece_letter(color: Color) -> char {
+    match PieceType::ALL
+        .into_iter()
+        .find(|piece_type| piece_color(*piece_type) == color)
+    {
+        Some(PieceType::I) => 'I',
+        Some(PieceType::J) => 'J',
+        Some(PieceType::L) => 'L',
+        Some(PieceType::O) => 'O',
+        Some(PieceType::S) => 'S',
+        Some(PieceType::T) => 'T',
+        Some(PieceType::Z) => 'Z',
+        _ => 'G',
+    }
+}
+
+fn letter_color(letter: char) -> Color {
+    let piece_type = match letter {
+        'I' => PieceType::I,
+        'J' => PieceType::J,
+        'L' => PieceType::L,
+        'O' => PieceType::O,
+        'S' => PieceType::S,
+        'T' => PieceType::T,
+        'Z' => PieceType::Z,
+        _ => return GARBAGE_COLOR,
+    };
+    piece_color(piece_type)
+}
+
+// 当前骨牌及预览
+fn tbp_queue(game: &Game) -> Vec<PieceType> {
+    game.piece
+        .iter()
+        .map(|piece| piece.piece_type)
+        .chain(game.queue.iter().copied())
+        .take(TBP_QUEUE_LEN)
+        .collect()
+}
+
+pub fn start_message(game: &Game) -> Start {
+    let mut board = vec![vec![None; game.board_size.cols as usize]; TBP_BOARD_ROWS];
+    for (color, block) in &game.board {
+        if let Some(row) = board.get_mut(block.y as usize) {
+            row[block.x as usize] = Some(piece_letter(*color));
+        }
+    }
+    Start {
+        hold: None,
+        queue: tbp_queue(game),
+        combo: game.combo,
+        back_to_back: game.back_to_back,
+        board,
+    }
+}
+
+// 取出已知的下一个骨牌，不使用随机补充的骨牌
+pub fn spawn_known_piece(game: &mut Game) -> bool {
+    let known = game.queue.len();
+    let spawned = game.spawn_piece();
+    game.queue.truncate(known.saturating_sub(1));
+    spawned
+}
+
+// 按开始消息建立对局，供机器人一方使用
+pub fn game_from_start(start: &Start, board_size: BoardSize) -> Game {
+    let mut game = Game::new(board_size, 0);
+    game.board = start
+        .board
+        .iter()
+        .enumerate()
+        .take(board_size.rows as usize)
+        .flat_map(|(y, row)| {
+            row.iter().enumerate().filter_map(move |(x, cell)| {
+                cell.map(|letter| {
+                    (
+                        letter_color(letter),
+                        Block {
+                            x: x as i32,
+                            y: y as i32,
+                        },
+                    )
+                })
+            })
+        })
+        .collect();
+    game.queue = start.queue.iter().copied().collect();
+    game.combo = start.combo;
+    game.back_to_back = start.back_to_back;
+    spawn_known_piece(&mut game);
+    game
+}
+
+// 外部机器人进程
+pub struct TbpBot {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Mutex<Receiver<BotMessage>>,
+    ready: bool,
+    started: bool,
+    // 机器人所知的面板和骨牌队列，与实际对局不一致时重新开始
+    session: Option<(Vec<Block>, Vec<PieceType>)>,
+    waiting: bool,
+}
+
+impl std::fmt::Debug for TbpBot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TbpBot")
+            .field("pid", &self.child.id())
+            .field("ready", &self.ready)
+            .field("waiting", &self.waiting)
+            .finish()
+    }
+}
+
+fn sorted_board(game: &Game) -> Vec<Block> {
+    let mut board = game.board_blocks();
+    board.sort_by_key(|block| (block.y, block.x));
+    board
+}
+
+impl TbpBot {
+    // 启动机器人进程，在单独的线程中读取它的输出
+    pub fn spawn(program: &str) -> io::Result<Self> {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                match serde_json::from_str::<BotMessage>(&line) {
+                    Ok(message) => {
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => warn!("tbp bot: invalid message {:?}: {}", line, err),
+                }
+            }
+        });
+        info!("tbp bot: started {}", program);
+        Ok(TbpBot {
+            child,
+            stdin,
+            messages: Mutex::new(receiver),
+            ready: false,
+            started: false,
+            session: None,
+            waiting: false,
+        })
+    }
+
+    fn send(&mut self, message: &FrontendMessage) {
+        let result = serde_json::to_string(message)
+            .map_err(io::Error::other)
+            .and_then(|line| writeln!(self.stdin, "{}", line))
+            .and_then(|_| self.stdin.flush());
+        if let Err(err) = result {
+            warn!("tbp bot: failed to send {:?}: {}", message, err);
+        }
+    }
+
+    // 告知机器人当前骨牌并请求落点，面板与机器人所知的不同时（如收到垃圾行）重新开始
+    fn request(&mut self, game: &Game) {
+        let board = sorted_board(game);
+        let queue = tbp_queue(game);
+        let new_pieces = match &self.session {
+            Some((known_board, known_queue))
+                if *known_board == board && queue.starts_with(known_queue) =>
+            {
+                Some(queue[known_queue.len()..].to_vec())
+            }
+            _ => None,
+        };
+        if let Some(new_pieces) = new_pieces {
+            for piece in new_pieces {
+                self.send(&FrontendMessage::NewPiece { piece });
+            }
+        } else {
+            if self.started {
+                self.send(&FrontendMessage::Stop);
+            }
+            self.send(&FrontendMessage::Start(start_message(game)));
+            self.started = true;
+        }
+        self.session = Some((board, queue));
+        self.send(&FrontendMessage::Suggest);
+        self.waiting = true;
+    }
+
+    // 处理机器人的消息，当前骨牌还没有请求落点时发送请求
+    // 收到建议时返回第一个可以到达的落点，都不能到达时使用内置搜索的第一个落点
+    pub fn poll(&mut self, game: &Game) -> Option<Placement> {
+        let messages: Vec<BotMessage> = self
+            .messages
+            .get_mut()
+            .map(|receiver| receiver.try_iter().collect())
+            .unwrap_or_default();
+        let mut suggestion = None;
+        for message in messages {
+            match message {
+                BotMessage::Info {
+                    name,
+                    version,
+                    author,
+                    ..
+                } => {
+                    info!("tbp bot: {} {} by {}", name, version, author);
+                    self.send(&FrontendMessage::Rules);
+                }
+                BotMessage::Ready => self.ready = true,
+                BotMessage::Error { reason } => warn!("tbp bot: error {}", reason),
+                BotMessage::Suggestion { moves } if self.waiting => {
+                    self.waiting = false;
+                    suggestion = Some(moves);
+                }
+                BotMessage::Suggestion { .. } => {}
+            }
+        }
+
+        let piece_type = game.piece.as_ref()?.piece_type;
+        if let Some(moves) = suggestion {
+            let found = moves
+                .iter()
+                .find_map(|mv| find_placement(game, mv).map(|placement| (*mv, placement)));
+            let Some((mv, placement)) = found else {
+                warn!("tbp bot: no valid move in {:?}", moves);
+                self.session = None;
+                return placements(game).into_iter().next();
+            };
+            self.send(&FrontendMessage::Play { mv });
+            let (after, _) = simulate(game, &placement);
+            if let Some((board, queue)) = &mut self.session {
+                *board = sorted_board(&after);
+                if queue.first() == Some(&piece_type) {
+                    queue.remove(0);
+                }
+            }
+            return Some(placement);
+        }
+        if self.ready && !self.waiting {
+            self.request(game);
+        }
+        None
+    }
+}
+
+impl Drop for TbpBot {
+    fn drop(&mut self) {
+        self.send(&FrontendMessage::Quit);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// 启动参数中指定的外部机器人：--tbp-bot <程序路径>
+#[derive(Debug, Default, Resource)]
+pub struct TbpBotCommand(pub Option<String>);
+
+impl TbpBotCommand {
+    pub fn from_args() -> Self {
+        let mut args = std::env::args().skip_while(|arg| arg != "--tbp-bot");
+        args.next();
+        TbpBotCommand(args.next())
+    }
+
+    // 启动外部机器人，未指定或启动失败时返回None
+    pub fn spawn(&self) -> Option<TbpBot> {
+        let program = self.0.as_ref()?;
+        TbpBot::spawn(program)
+            .inspect_err(|err| warn!("tbp bot: failed to start {}: {}", program, err))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn t_move() -> Move {
+        Move {
+            location: PieceLocation {
+                piece_type: PieceType::T,
+                orientation: Orientation::South,
+                x: 4,
+                y: 1,
+            },
+            spin: Spin::Full,
+        }
+    }
+
+    #[test]
+    fn frontend_messages_follow_protocol() {
+        let play = FrontendMessage::Play { mv: t_move() };
+        assert_eq!(
+            serde_json::to_value(&play).unwrap(),
+            json!({
+                "type": "play",
+                "move": {
+                    "location": {"type": "T", "orientation": "south", "x": 4, "y": 1},
+                    "spin": "full",
+                },
+            })
+        );
+        let new_piece = FrontendMessage::NewPiece {
+            piece: PieceType::S,
+        };
+        assert_eq!(
+            serde_json::to_value(&new_piece).unwrap(),
+            json!({"type": "new_piece", "piece": "S"})
+        );
+        assert_eq!(
+            serde_json::to_value(FrontendMessage::Suggest).unwrap(),
+            json!({"type": "suggest"})
+        );
+    }
+
+    #[test]
+    fn bot_messages_follow_protocol() {
+        let info = r#"{"type":"info","name":"bot","version":"1.0","author":"someone"}"#;
+        let BotMessage::Info { name, features, .. } = serde_json::from_str(info).unwrap() else {
+            panic!("expected info");
+        };
+        assert_eq!(name, "bot");
+        assert!(features.is_empty());
+
+        let suggestion = json!({
+            "type": "suggestion",
+            "moves": [{
+                "location": {"type": "T", "orientation": "south", "x": 4, "y": 1},
+                "spin": "full",
+            }],
+        });
+        let BotMessage::Suggestion { moves } = serde_json::from_value(suggestion).unwrap() else {
+            panic!("expected suggestion");
+        };
+        assert_eq!(moves, vec![t_move()]);
+        assert!(matches!(
+            serde_json::from_str(r#"{"type":"ready"}"#).unwrap(),
+            BotMessage::Ready
+        ));
+    }
+
+    #[test]
+    fn start_message_round_trip() {
+        let mut game = Game::new(BoardSize::default(), 3);
+        game.board = vec![
+            (GARBAGE_COLOR, Block { x: 0, y: 0 }),
+            (piece_color(PieceType::T), Block { x: 9, y: 1 }),
+        ];
+        game.combo = 2;
+        game.back_to_back = true;
+        assert!(spawn_known_piece(&mut game));
+
+        let start = start_message(&game);
+        let value = serde_json::to_value(FrontendMessage::Start(start.clone())).unwrap();
+        assert_eq!(value["type"], "start");
+        assert_eq!(value["hold"], serde_json::Value::Null);
+        assert_eq!(value["board"].as_array().unwrap().len(), TBP_BOARD_ROWS);
+        assert_eq!(value["board"][0][0], "G");
+        assert_eq!(value["board"][1][9], "T");
+        assert_eq!(value["board"][1][0], serde_json::Value::Null);
+
+        let FrontendMessage::Start(start) = serde_json::from_value(value).unwrap() else {
+            panic!("expected start");
+        };
+        let received = game_from_start(&start, BoardSize::default());
+        assert_eq!(received.board, game.board);
+        assert_eq!(
+            received.piece.as_ref().map(|piece| &piece.blocks),
+            game.piece.as_ref().map(|piece| &piece.blocks)
+        );
+        assert_eq!(tbp_queue(&received), start.queue[..]);
+        assert_eq!(received.combo, 2);
+        assert!(received.back_to_back);
+    }
+
+    #[test]
+    fn placement_move_round_trip() {
+        for piece_type in PieceType::ALL {
+            let mut game = Game::new(BoardSize::default(), 0);
+            game.queue.push_front(piece_type);
+            assert!(game.spawn_piece());
+
+            // 每个旋转状态都对应TBP中形状相同的朝向
+            for (turns, orientation) in ORIENTATIONS.iter().enumerate() {
+                let mut rotated = game.clone();
+                for _ in 0..turns {
+                    assert!(rotated.rotate_piece());
+                }
+                rotated.drop_piece(rotated.drop_distance());
+                let piece = rotated.piece.unwrap();
+                let mut blocks = piece.blocks;
+                blocks.sort_by_key(|block| (block.y, block.x));
+                let placement = Placement {
+                    blocks,
+                    state: piece.state,
+                    inputs: Vec::new(),
+                    t_spin: false,
+                };
+                let mv = placement_move(piece_type, &placement).unwrap();
+                assert_eq!(mv.location.orientation, *orientation);
+                let found = find_placement(&game, &mv).unwrap();
+                assert_eq!(found.blocks, placement.blocks);
+            }
+
+            for placement in placements(&game) {
+                let mv = placement_move(piece_type, &placement).unwrap();
+                assert_eq!(location_blocks(&mv.location).unwrap(), placement.blocks);
+                let found = find_placement(&game, &mv).unwrap();
+                assert_eq!(found.blocks, placement.blocks);
+                assert_eq!(found.t_spin, placement.t_spin);
+            }
+        }
+    }
+}
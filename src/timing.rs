@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::common::GameMode;
+
+// 经典模式下的各项延迟
+pub const NORMAL_GRAVITY: Duration = Duration::from_millis(1000);
+pub const NORMAL_SOFT_DROP: Duration = Duration::from_millis(100);
+pub const NORMAL_LOCK_DELAY: Duration = Duration::from_millis(300);
+pub const NORMAL_DAS: Duration = Duration::from_millis(100);
+pub const NORMAL_ARR: Duration = Duration::from_millis(100);
+
+// 以60帧每秒换算帧数
+pub const fn frames(count: u64) -> Duration {
+    Duration::from_micros(count * 1_000_000 / 60)
+}
+
+// 游戏节奏参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct GameTimings {
+    // 自动下落一格的间隔，为零时骨牌直接落到底部（20G），为None时不自动下落
+    pub gravity: Option<Duration>,
+    // 按住向下键时下落一格的间隔
+    pub soft_drop: Duration,
+    // 出块延迟（ARE），骨牌锁定后到下一个骨牌出现的时间
+    pub are: Duration,
+    // 消行延迟，消行后额外等待的时间
+    pub line_clear_delay: Duration,
+    // 锁定延迟，骨牌着地后仍可移动的时间
+    pub lock_delay: Duration,
+    // 长按左右键后开始自动移动的延迟（DAS）
+    pub das: Duration,
+    // 自动移动的间隔（ARR）
+    pub arr: Duration,
+}
+
+impl Default for GameTimings {
+    fn default() -> Self {
+        GameTimings {
+            gravity: Some(NORMAL_GRAVITY),
+            soft_drop: NORMAL_SOFT_DROP,
+            are: Duration::ZERO,
+            line_clear_delay: Duration::ZERO,
+            lock_delay: NORMAL_LOCK_DELAY,
+            das: NORMAL_DAS,
+            arr: NORMAL_ARR,
+        }
+    }
+}
+
+// 游戏计时器，统一管理下落、锁定、出块、消行和长按移动
+#[derive(Debug, Resource)]
+pub struct GameTimers {
+    // 本局游戏进行的时长，暂停时不计
+    pub elapsed: Duration,
+    pub gravity: Timer,
+    pub soft_drop: Timer,
+    pub are: Timer,
+    pub line_clear: Timer,
+    pub lock: Timer,
+    pub das: Timer,
+    pub arr: Timer,
+}
+
+impl GameTimers {
+    pub fn new(timings: &GameTimings) -> Self {
+        let mut timers = GameTimers {
+            elapsed: Duration::ZERO,
+            gravity: Timer::new(timings.gravity.unwrap_or_default(), TimerMode::Repeating),
+            soft_drop: Timer::new(timings.soft_drop, TimerMode::Repeating),
+            are: Timer::new(timings.are, TimerMode::Once),
+            line_clear: Timer::new(timings.line_clear_delay, TimerMode::Once),
+            lock: Timer::new(timings.lock_delay, TimerMode::Once),
+            das: Timer::new(timings.das, TimerMode::Once),
+            arr: Timer::new(timings.arr, TimerMode::Repeating),
+        };
+        // 开局时没有待处理的消行
+        finish_timer(&mut timers.line_clear);
+        timers
+    }
+
+    // 更新各计时器时长，保留已经过的时间
+    pub fn apply(&mut self, timings: &GameTimings) {
+        self.gravity
+            .set_duration(timings.gravity.unwrap_or_default());
+        self.soft_drop.set_duration(timings.soft_drop);
+        self.are.set_duration(timings.are);
+        self.line_clear.set_duration(timings.line_clear_delay);
+        self.lock.set_duration(timings.lock_delay);
+        self.das.set_duration(timings.das);
+        self.arr.set_duration(timings.arr);
+    }
+}
+
+impl Default for GameTimers {
+    fn default() -> Self {
+        GameTimers::new(&GameTimings::default())
+    }
+}
+
+// 将计时器直接置为完成状态
+pub fn finish_timer(timer: &mut Timer) {
+    let remaining = timer.remaining();
+    timer.tick(remaining);
+}
+
+pub fn tick_game_elapsed(time: Res<Time>, mut timers: ResMut<GameTimers>) {
+    timers.elapsed += time.delta();
+}
+
+// 节奏参数变化时同步到计时器
+pub fn apply_game_timings(timings: Res<GameTimings>, mut timers: ResMut<GameTimers>) {
+    if timings.is_changed() {
+        timers.apply(&timings);
+    }
+}
+
+// 禅模式下按G键开关重力
+pub fn toggle_gravity(keyboard_input: Res<ButtonInput<KeyCode>>, mut timings: ResMut<GameTimings>) {
+    if keyboard_input.just_pressed(KeyCode::KeyG) {
+        timings.gravity = match timings.gravity {
+            Some(_) => None,
+            None => Some(NORMAL_GRAVITY),
+        };
+        info!("gravity: {:?}", timings.gravity);
+    }
+}
+
+pub fn reset_game_timings(
+    game_mode: Res<GameMode>,
+    mut timings: ResMut<GameTimings>,
+    mut timers: ResMut<GameTimers>,
+) {
+    *timings = match *game_mode {
+        // 禅模式默认没有重力
+        GameMode::Zen => GameTimings {
+            gravity: None,
+            ..default()
+        },
+        _ => GameTimings::default(),
+    };
+    *timers = GameTimers::new(&timings);
+}
@@ -0,0 +1,163 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::board::*;
+use crate::common::GameMode;
+
+// 重力翻转和面板旋转的间隔
+pub const GRAVITY_FLIP_INTERVAL: Duration = Duration::from_secs(20);
+pub const BOARD_TURN_INTERVAL: Duration = Duration::from_secs(30);
+// 翻转和旋转动画的速度
+const FLIP_SPEED: f32 = 4.0;
+const TURN_SPEED: f32 = PI;
+
+// 面板在屏幕上的显示方式，只影响渲染和左右按键，不改变逻辑坐标
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct BoardView {
+    // 左右镜像
+    pub mirrored: bool,
+    // 上下翻转，骨牌看起来向上落
+    pub flipped: bool,
+    // 旋转180°
+    pub turned: bool,
+}
+
+impl BoardView {
+    pub fn new(game_mode: GameMode) -> Self {
+        BoardView {
+            mirrored: game_mode == GameMode::Mirror,
+            ..default()
+        }
+    }
+
+    // 屏幕上的左右方向是否与逻辑坐标相反
+    pub fn flips_x(&self) -> bool {
+        self.mirrored != self.turned
+    }
+
+    fn scale(&self) -> Vec3 {
+        Vec3::new(
+            if self.mirrored { -1.0 } else { 1.0 },
+            if self.flipped { -1.0 } else { 1.0 },
+            1.0,
+        )
+    }
+
+    fn angle(&self) -> f32 {
+        if self.turned { PI } else { 0.0 }
+    }
+
+    pub fn transform(&self) -> Transform {
+        Transform {
+            rotation: Quat::from_rotation_z(self.angle()),
+            scale: self.scale(),
+            ..default()
+        }
+    }
+}
+
+// 变化模式的计时器
+#[derive(Debug, Resource)]
+pub struct VariantTimer(pub Timer);
+
+impl Default for VariantTimer {
+    fn default() -> Self {
+        VariantTimer(Timer::new(GRAVITY_FLIP_INTERVAL, TimerMode::Repeating))
+    }
+}
+
+// 面板方块和边界的父实体，其Transform即面板坐标到屏幕的变换
+#[derive(Debug, Component)]
+pub struct BoardRoot {
+    // 当前显示的旋转角度
+    pub angle: f32,
+}
+
+pub fn setup_board_root(mut commands: Commands) {
+    commands.spawn((
+        BoardRoot { angle: 0.0 },
+        Transform::default(),
+        Visibility::Visible,
+    ));
+}
+
+// 新生成的面板方块和边界挂到BoardRoot下
+pub fn attach_to_board_root(
+    mut commands: Commands,
+    q_root: Single<Entity, With<BoardRoot>>,
+    q_added: Query<Entity, Or<(Added<Block>, Added<BoardBorder>)>>,
+) {
+    for entity in &q_added {
+        commands.entity(entity).insert(ChildOf(*q_root));
+    }
+}
+
+// 开局时根据模式设置显示方式，立即生效不播放动画
+pub fn reset_board_view(
+    game_mode: Res<GameMode>,
+    mut board_view: ResMut<BoardView>,
+    mut variant_timer: ResMut<VariantTimer>,
+    mut q_root: Query<(&mut BoardRoot, &mut Transform)>,
+) {
+    *board_view = BoardView::new(*game_mode);
+    let interval = match *game_mode {
+        GameMode::BoardTurn => BOARD_TURN_INTERVAL,
+        _ => GRAVITY_FLIP_INTERVAL,
+    };
+    variant_timer.0 = Timer::new(interval, TimerMode::Repeating);
+    for (mut board_root, mut transform) in &mut q_root {
+        board_root.angle = 0.0;
+        *transform = board_view.transform();
+    }
+}
+
+// 到时间后翻转重力或旋转面板
+pub fn toggle_board_variant(
+    game_mode: Res<GameMode>,
+    mut board_view: ResMut<BoardView>,
+    mut variant_timer: ResMut<VariantTimer>,
+    time: Res<Time>,
+) {
+    variant_timer.0.tick(time.delta());
+    if !variant_timer.0.just_finished() {
+        return;
+    }
+    match *game_mode {
+        GameMode::GravityFlip => board_view.flipped = !board_view.flipped,
+        GameMode::BoardTurn => board_view.turned = !board_view.turned,
+        _ => return,
+    }
+    info!("board view: {:?}", *board_view);
+}
+
+// 面板逐渐变换到目标显示方式
+pub fn animate_board_root(
+    board_view: Res<BoardView>,
+    q_root: Single<(&mut BoardRoot, &mut Transform)>,
+    time: Res<Time>,
+) {
+    let (mut board_root, mut transform) = q_root.into_inner();
+    let target = board_view.transform();
+    if *transform == target {
+        return;
+    }
+    let delta = time.delta_secs();
+    let scale = board_view.scale();
+    transform.scale.x = approach(transform.scale.x, scale.x, FLIP_SPEED * delta);
+    transform.scale.y = approach(transform.scale.y, scale.y, FLIP_SPEED * delta);
+    board_root.angle = approach(board_root.angle, board_view.angle(), TURN_SPEED * delta);
+    transform.rotation = Quat::from_rotation_z(board_root.angle);
+    if transform.scale == scale && board_root.angle == board_view.angle() {
+        *transform = target;
+    }
+}
+
+fn approach(current: f32, target: f32, step: f32) -> f32 {
+    if (target - current).abs() <= step {
+        target
+    } else {
+        current + step * (target - current).signum()
+    }
+}
@@ -0,0 +1,615 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::attack::*;
+use crate::board::*;
+use crate::bot::BotWeights;
+use crate::common::*;
+use crate::cpu::*;
+use crate::engine::*;
+use crate::menu::OnGameOverMenuScreen;
+use crate::piece::*;
+use crate::stats::StatsBoardRow;
+use crate::survival::{GARBAGE_METER_GAP, GARBAGE_METER_WIDTH};
+use crate::tbp::TbpBotCommand;
+use crate::timing::*;
+use crate::variant::BoardRoot;
+
+// 两个面板中心到屏幕中心的水平距离
+const PLAYER_BOARD_OFFSET: f32 = 300.0;
+// 每个玩家预览的骨牌数
+const PREVIEW_PIECES: usize = 3;
+// 垃圾行警示条每格占一行高度的比例，留出间隙便于数清行数
+const GARBAGE_METER_CELL_RATIO: f32 = 0.8;
+// 垃圾行被抵消时闪烁消失的时长
+const GARBAGE_CANCEL_FLASH: Duration = Duration::from_millis(300);
+
+// 玩家编号，从0开始，标记玩家面板及其显示的方块
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct Player(pub usize);
+
+impl Player {
+    pub fn name(&self) -> String {
+        format!("P{}", self.0 + 1)
+    }
+}
+
+// 玩家的对局状态
+#[derive(Debug, Component)]
+pub struct PlayerGame(pub Game);
+
+// 玩家的计时器，各项延迟与经典模式相同
+#[derive(Debug, Component)]
+pub struct PlayerTimers(pub GameTimers);
+
+// 玩家按键
+#[derive(Debug, Clone, Copy, Component)]
+pub struct PlayerControls {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub rotate: KeyCode,
+    pub soft_drop: KeyCode,
+    pub hard_drop: KeyCode,
+}
+
+// 玩家1使用WASD和空格，玩家2使用方向键和回车
+pub const PLAYER_CONTROLS: [PlayerControls; 2] = [
+    PlayerControls {
+        left: KeyCode::KeyA,
+        right: KeyCode::KeyD,
+        rotate: KeyCode::KeyW,
+        soft_drop: KeyCode::KeyS,
+        hard_drop: KeyCode::Space,
+    },
+    PlayerControls {
+        left: KeyCode::ArrowLeft,
+        right: KeyCode::ArrowRight,
+        rotate: KeyCode::ArrowUp,
+        soft_drop: KeyCode::ArrowDown,
+        hard_drop: KeyCode::Enter,
+    },
+];
+
+// 人机对战时玩家使用与单人模式相同的按键
+pub const SOLO_CONTROLS: PlayerControls = PlayerControls {
+    left: KeyCode::ArrowLeft,
+    right: KeyCode::ArrowRight,
+    rotate: KeyCode::ArrowUp,
+    soft_drop: KeyCode::ArrowDown,
+    hard_drop: KeyCode::Space,
+};
+
+// 玩家面板上显示的方块
+#[derive(Debug, Component)]
+pub struct PlayerCell;
+
+// 玩家面板上已显示的方块，按(是否为预览, 位置)索引，对局状态变化时只更新变化的格子
+#[derive(Debug, Default, Component)]
+pub struct PlayerCells(HashMap<(bool, i32, i32), (Entity, Color)>);
+
+// 玩家面板下方的统计
+#[derive(Debug, Component)]
+pub struct PlayerStatsText;
+
+// 玩家面板左侧的垃圾行警示条，每行等待插入的垃圾行对应一格
+#[derive(Debug, Component)]
+pub struct IncomingGarbageMeter {
+    // 已展示过的抵消行数
+    cancelled: u32,
+    // 正在闪烁消失的行数
+    flash_rows: u32,
+    flash: Timer,
+}
+
+// 警示条上的一格，从下往上编号
+#[derive(Debug, Component)]
+pub struct IncomingGarbageCell(pub usize);
+
+// 对战结果，winner为None时为平局
+#[derive(Debug, Default, Resource)]
+pub struct VersusResult {
+    pub winner: Option<usize>,
+    pub stats: Vec<(GameStats, Duration)>,
+}
+
+// 双人对战或人机对战
+pub fn in_versus(game_mode: Res<GameMode>) -> bool {
+    game_mode.is_versus()
+}
+
+fn pieces_per_second(stats: &GameStats, elapsed: Duration) -> f32 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        stats.pieces as f32 / elapsed.as_secs_f32()
+    }
+}
+
+fn stats_info(player: Player, stats: &GameStats, elapsed: Duration) -> String {
+    format!(
+        "{}  Lines: {}  Attack: {}  Pieces: {}  PPS: {:.2}",
+        player.name(),
+        stats.lines,
+        stats.attack,
+        stats.pieces,
+        pieces_per_second(stats, elapsed)
+    )
+}
+
+// 玩家面板的边界，不使用BoardBorder组件，避免挂到单人面板下
+pub fn spawn_player_borders(parent: &mut ChildSpawnerCommands, board_size: &BoardSize) {
+    for border in [
+        BoardBorder::Left,
+        BoardBorder::Right,
+        BoardBorder::Top,
+        BoardBorder::Bottom,
+    ] {
+        parent.spawn((
+            border.transform(board_size),
+            Sprite {
+                color: BORDER_COLOR,
+                ..default()
+            },
+        ));
+    }
+}
+
+// 开局时生成两个玩家的面板，非对战模式下恢复单人面板的显示
+pub fn setup_versus_boards(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    board_size: Res<BoardSize>,
+    cpu_difficulty_setting: Res<CpuDifficultySetting>,
+    tbp_bot_command: Res<TbpBotCommand>,
+    bot_weights_handle: Res<BotWeightsHandle>,
+    bot_weights: Res<Assets<BotWeights>>,
+    q_player: Query<Entity, With<Player>>,
+    mut q_board_root: Query<&mut Visibility, With<BoardRoot>>,
+    mut q_stats: Query<&mut Visibility, (With<StatsBoardRow>, Without<BoardRoot>)>,
+) {
+    for entity in &q_player {
+        commands.entity(entity).despawn();
+    }
+    let visibility = if game_mode.is_versus() {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    };
+    for mut root_visibility in q_board_root.iter_mut().chain(q_stats.iter_mut()) {
+        *root_visibility = visibility;
+    }
+    if !game_mode.is_versus() {
+        return;
+    }
+    // 两个玩家使用相同的骨牌序列
+    let seed = rand::rng().random();
+    for (index, controls) in PLAYER_CONTROLS.iter().enumerate() {
+        let player = Player(index);
+        let x = if index == 0 {
+            -PLAYER_BOARD_OFFSET
+        } else {
+            PLAYER_BOARD_OFFSET
+        };
+        let game = Game::new(*board_size, seed);
+        let mut player_entity = commands.spawn((
+            player,
+            Transform::from_xyz(x, 0.0, 0.0),
+            Visibility::Visible,
+            PlayerTimers(GameTimers::new(&GameTimings::default())),
+            PlayerCells::default(),
+        ));
+        match (*game_mode, index) {
+            (GameMode::VersusCpu, 0) => player_entity.insert(SOLO_CONTROLS),
+            // 指定了外部机器人时由它操作电脑玩家
+            (GameMode::VersusCpu, _) => player_entity.insert(
+                CpuPlayer::new(cpu_difficulty_setting.0)
+                    .with_weights(current_bot_weights(&bot_weights_handle, &bot_weights))
+                    .with_external(tbp_bot_command.spawn()),
+            ),
+            _ => player_entity.insert(*controls),
+        };
+        player_entity
+            .with_children(|parent| {
+                spawn_player_borders(parent, &board_size);
+                // 位于左侧边界外，先插入的垃圾行显示在下方
+                parent
+                    .spawn((
+                        Transform::from_xyz(
+                            -board_size.width() / 2.0
+                                - BORDER_THICKNESS
+                                - GARBAGE_METER_GAP
+                                - GARBAGE_METER_WIDTH / 2.0,
+                            0.0,
+                            0.0,
+                        ),
+                        Visibility::Inherited,
+                        IncomingGarbageMeter {
+                            cancelled: 0,
+                            flash_rows: 0,
+                            flash: Timer::new(GARBAGE_CANCEL_FLASH, TimerMode::Once),
+                        },
+                    ))
+                    .with_children(|meter| {
+                        let block_length = board_size.block_length();
+                        for index in 0..board_size.rows as usize {
+                            meter.spawn((
+                                Transform {
+                                    translation: Vec3::new(
+                                        0.0,
+                                        -board_size.height() / 2.0
+                                            + (index as f32 + 0.5) * block_length,
+                                        0.0,
+                                    ),
+                                    scale: Vec3::new(
+                                        GARBAGE_METER_WIDTH,
+                                        block_length * GARBAGE_METER_CELL_RATIO,
+                                        0.0,
+                                    ),
+                                    ..default()
+                                },
+                                Sprite::default(),
+                                Visibility::Hidden,
+                                IncomingGarbageCell(index),
+                            ));
+                        }
+                    });
+                parent.spawn((
+                    Text2d::new(stats_info(player, &game.stats, Duration::ZERO)),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                    Transform::from_xyz(0.0, -board_size.height() / 2.0 - 30.0, 0.0),
+                    PlayerStatsText,
+                ));
+            })
+            .insert(PlayerGame(game));
+    }
+}
+
+// 按玩家的按键操作骨牌，返回锁定时的消行
+fn play_human(
+    game: &mut Game,
+    timers: &mut GameTimers,
+    controls: &PlayerControls,
+    keyboard_input: &ButtonInput<KeyCode>,
+    delta: Duration,
+    changed: &mut bool,
+) -> Option<LinesCleared> {
+    let (delta_x, _) = tick_das(
+        keyboard_input,
+        (controls.left, controls.right),
+        timers,
+        delta,
+    );
+    if delta_x != 0 {
+        *changed |= game.move_piece(delta_x);
+    }
+    if keyboard_input.just_pressed(controls.rotate) {
+        *changed |= game.rotate_piece();
+    }
+
+    if keyboard_input.just_pressed(controls.hard_drop) {
+        return game.hard_drop();
+    }
+    timers.gravity.tick(delta);
+    let gravity_rows = timers.gravity.times_finished_this_tick() as i32;
+    let mut soft_drop_rows = 0;
+    if keyboard_input.just_pressed(controls.soft_drop) {
+        timers.soft_drop.reset();
+        soft_drop_rows = 1;
+    } else if keyboard_input.pressed(controls.soft_drop) {
+        timers.soft_drop.tick(delta);
+        soft_drop_rows = timers.soft_drop.times_finished_this_tick() as i32;
+    }
+    *changed |= game.drop_piece(gravity_rows.max(soft_drop_rows)) > 0;
+    if game.drop_distance() > 0 {
+        timers.lock.reset();
+        return None;
+    }
+    // 着地后锁定延迟内仍可移动，按住向下键立即锁定
+    timers.lock.tick(delta);
+    if timers.lock.finished() || keyboard_input.pressed(controls.soft_drop) {
+        game.lock_piece()
+    } else {
+        None
+    }
+}
+
+// 操作各个玩家的骨牌，人类玩家使用各自的按键，电脑玩家按搜索结果操作，
+// 消行攻击在所有玩家操作后发送给对方
+pub fn play_versus(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    attack_table_handle: Res<AttackTableHandle>,
+    attack_tables: Res<Assets<AttackTable>>,
+    mut q_player: Query<(
+        &Player,
+        &mut PlayerGame,
+        &mut PlayerTimers,
+        Option<&PlayerControls>,
+        Option<&mut CpuPlayer>,
+    )>,
+    time: Res<Time>,
+) {
+    let default_table = AttackTable::default();
+    let attack_table = attack_tables
+        .get(&attack_table_handle.0)
+        .unwrap_or(&default_table);
+    let mut attacks = Vec::new();
+    for (player, mut player_game, mut timers, controls, cpu) in &mut q_player {
+        let timers = &mut timers.0;
+        timers.elapsed += time.delta();
+        // 只在对局状态变化时标记，避免每帧重新生成方块
+        let game = &mut player_game.bypass_change_detection().0;
+        game.tick_garbage(time.delta());
+        let mut changed = false;
+        if game.piece.is_none() {
+            changed = true;
+            timers.gravity.reset();
+            if !game.spawn_piece() {
+                player_game.set_changed();
+                continue;
+            }
+        }
+        let locked = if let Some(mut cpu) = cpu {
+            let (moved, locked) = cpu.play(game, time.delta());
+            changed |= moved;
+            locked
+        } else if let Some(controls) = controls {
+            play_human(
+                game,
+                timers,
+                controls,
+                &keyboard_input,
+                time.delta(),
+                &mut changed,
+            )
+        } else {
+            None
+        };
+        if let Some(lines_cleared) = locked {
+            changed = true;
+            timers.lock.reset();
+            if lines_cleared.count > 0 {
+                commands.spawn(AudioPlayer(game_audios.line_clear.clone()));
+            }
+            let attack = game.resolve_attack(&lines_cleared, attack_table);
+            if attack > 0 {
+                info!("versus attack: {} sends {}", player.name(), attack);
+                attacks.push((*player, attack));
+            }
+        }
+        if changed {
+            player_game.set_changed();
+        }
+    }
+    for (sender, attack) in attacks {
+        for (player, mut player_game, _, _, _) in &mut q_player {
+            if *player != sender {
+                player_game
+                    .bypass_change_detection()
+                    .0
+                    .receive_garbage(attack, attack_table.garbage_delay());
+            }
+        }
+    }
+}
+
+// 有玩家堆到顶部时结束对战
+pub fn check_versus_finished(
+    mut commands: Commands,
+    game_audios: Res<GameAudios>,
+    mut versus_result: ResMut<VersusResult>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    q_player: Query<(&Player, &PlayerGame, &PlayerTimers)>,
+) {
+    let mut players: Vec<_> = q_player.iter().collect();
+    if !players.iter().any(|(_, game, _)| game.0.topped_out) {
+        return;
+    }
+    players.sort_by_key(|(player, _, _)| player.0);
+    let survivors: Vec<usize> = players
+        .iter()
+        .filter(|(_, game, _)| !game.0.topped_out)
+        .map(|(player, _, _)| player.0)
+        .collect();
+    *versus_result = VersusResult {
+        winner: (survivors.len() == 1).then(|| survivors[0]),
+        stats: players
+            .iter()
+            .map(|(_, game, timers)| (game.0.stats, timers.0.elapsed))
+            .collect(),
+    };
+    info!("versus finished: {:?}", *versus_result);
+    commands.spawn(AudioPlayer(game_audios.gameover.clone()));
+    app_state.set(AppState::GameOver);
+    game_state.set(GameState::GameQuited);
+}
+
+// 对局状态变化时更新面板方块、当前骨牌和预览，只生成、移除或改色变化的格子
+pub fn render_versus_boards(
+    mut commands: Commands,
+    mut q_player: Query<(Entity, &PlayerGame, &mut PlayerCells), Changed<PlayerGame>>,
+    mut q_sprite: Query<&mut Sprite, With<PlayerCell>>,
+) {
+    for (player_entity, player_game, mut player_cells) in &mut q_player {
+        let game = &player_game.0;
+        let board_size = &game.board_size;
+        let mut cells: HashMap<(bool, i32, i32), Color> = game
+            .board
+            .iter()
+            .map(|(color, block)| ((false, block.x, block.y), *color))
+            .collect();
+        if let Some(piece) = &game.piece {
+            let color = piece_color(piece.piece_type);
+            for block in piece
+                .blocks
+                .iter()
+                .filter(|block| block.y < board_size.rows)
+            {
+                cells.insert((false, block.x, block.y), color);
+            }
+        }
+        // 预览展示在面板右侧
+        for (index, piece_type) in game.queue.iter().take(PREVIEW_PIECES).enumerate() {
+            let blocks = shift_piece(
+                &piece_shape(*piece_type),
+                Some(board_size.cols - 2),
+                Some(board_size.rows - 3 - 3 * index as i32),
+            );
+            for block in blocks {
+                cells.insert((true, block.x, block.y), piece_color(*piece_type));
+            }
+        }
+
+        player_cells
+            .0
+            .retain(|key, (entity, color)| match cells.get(key) {
+                Some(new_color) => {
+                    if *new_color != *color {
+                        *color = *new_color;
+                        if let Ok(mut sprite) = q_sprite.get_mut(*entity) {
+                            sprite.color = *new_color;
+                        }
+                    }
+                    true
+                }
+                None => {
+                    commands.entity(*entity).despawn();
+                    false
+                }
+            });
+        for (key, color) in cells {
+            if player_cells.0.contains_key(&key) {
+                continue;
+            }
+            let (_, x, y) = key;
+            let entity = commands
+                .spawn((
+                    new_block_sprite(&Block { x, y }, color, Visibility::Inherited, board_size),
+                    PlayerCell,
+                    ChildOf(player_entity),
+                ))
+                .id();
+            player_cells.0.insert(key, (entity, color));
+        }
+    }
+}
+
+pub fn update_versus_stats(
+    q_player: Query<(&Player, &PlayerGame, &PlayerTimers, &Children)>,
+    mut q_text: Query<&mut Text2d, With<PlayerStatsText>>,
+) {
+    for (player, player_game, timers, children) in &q_player {
+        let info = stats_info(*player, &player_game.0.stats, timers.0.elapsed);
+        for child in children {
+            if let Ok(mut text) = q_text.get_mut(*child)
+                && text.0 != info
+            {
+                text.0 = info.clone();
+            }
+        }
+    }
+}
+
+// 按等待时间给垃圾行着色，越接近插入越红，被抵消的行闪烁后消失
+pub fn update_incoming_garbage_meters(
+    time: Res<Time>,
+    q_player: Query<&PlayerGame>,
+    mut q_meter: Query<(&mut IncomingGarbageMeter, &ChildOf, &Children)>,
+    mut q_cell: Query<(
+        &IncomingGarbageCell,
+        &mut Sprite,
+        &mut Transform,
+        &mut Visibility,
+    )>,
+) {
+    for (mut meter, child_of, children) in &mut q_meter {
+        let Ok(player_game) = q_player.get(child_of.parent()) else {
+            continue;
+        };
+        let game = &player_game.0;
+        if game.stats.cancelled > meter.cancelled {
+            meter.flash_rows = game.stats.cancelled - meter.cancelled;
+            meter.cancelled = game.stats.cancelled;
+            meter.flash.reset();
+        }
+        meter.flash.tick(time.delta());
+        let flash_rows = if meter.flash.finished() {
+            0
+        } else {
+            meter.flash_rows as usize
+        };
+        let fade = 1.0 - meter.flash.fraction();
+
+        let urgencies: Vec<f32> = game
+            .pending_garbage
+            .iter()
+            .flat_map(|garbage| std::iter::repeat_n(garbage.urgency(), garbage.rows as usize))
+            .collect();
+        let pending = game.pending_rows() as usize;
+        for child in children {
+            let Ok((cell, mut sprite, mut transform, mut visibility)) = q_cell.get_mut(*child)
+            else {
+                continue;
+            };
+            if let Some(urgency) = urgencies.get(cell.0) {
+                *visibility = Visibility::Inherited;
+                sprite.color = Color::srgb(1.0, 1.0 - urgency, 0.0);
+                transform.scale.x = GARBAGE_METER_WIDTH;
+            } else if cell.0 < pending + flash_rows {
+                // 抵消的行接在剩余垃圾行上方，变细并淡出
+                *visibility = Visibility::Inherited;
+                sprite.color = Color::srgba(1.0, 1.0, 1.0, fade);
+                transform.scale.x = GARBAGE_METER_WIDTH * fade;
+            } else {
+                *visibility = Visibility::Hidden;
+            }
+        }
+    }
+}
+
+// 对战结束时在菜单上方展示胜负和双方统计
+pub fn setup_versus_results(
+    mut commands: Commands,
+    game_mode: Res<GameMode>,
+    versus_result: Res<VersusResult>,
+) {
+    if !game_mode.is_versus() {
+        return;
+    }
+    let mut info = match versus_result.winner {
+        Some(winner) => format!("{} wins!", Player(winner).name()),
+        None => "Draw!".to_string(),
+    };
+    for (index, (stats, elapsed)) in versus_result.stats.iter().enumerate() {
+        info.push('\n');
+        info.push_str(&stats_info(Player(index), stats, *elapsed));
+    }
+    commands.spawn((
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            width: Val::Percent(100.0),
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        OnGameOverMenuScreen,
+        children![(
+            Text::new(info),
+            TextFont {
+                font_size: 25.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextLayout::new_with_justify(JustifyText::Center),
+        )],
+    ));
+}
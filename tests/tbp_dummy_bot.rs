@@ -0,0 +1,30 @@
+use std::time::{Duration, Instant};
+
+use tetris::board::BoardSize;
+use tetris::bot::*;
+use tetris::engine::Game;
+use tetris::tbp::*;
+
+// 与测试用的TBP机器人进程完整交换消息：开始、请求落点、放置和新骨牌
+#[test]
+fn plays_against_dummy_bot() {
+    let mut bot = TbpBot::spawn(env!("CARGO_BIN_EXE_tbp_dummy_bot")).unwrap();
+    let mut game = Game::new(BoardSize::default(), 11);
+    assert!(game.spawn_piece());
+    let deadline = Instant::now() + Duration::from_secs(30);
+    let mut played = 0;
+    while played < 10 {
+        assert!(Instant::now() < deadline, "bot stopped answering");
+        let Some(placement) = bot.poll(&game) else {
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        };
+        // 机器人与游戏的面板保持一致时，建议的就是内置搜索的最佳落点
+        let expected = best_placement(&game, 1, &BotWeights::default()).unwrap();
+        assert_eq!(placement.blocks, expected.blocks);
+        let (after, _) = simulate(&game, &placement);
+        game = after;
+        assert!(game.spawn_piece());
+        played += 1;
+    }
+}